@@ -1,72 +1,219 @@
 use std::{collections::HashSet, sync::Arc};
-use std::time::Instant;
+use std::path::Path;
 
 use crate::graphics::Graphics;
 use crate::game::Game;
+use crate::bindings::{ Action, Binding, Bindings };
 
 use winit::application::ApplicationHandler;
 use winit::dpi::{LogicalSize};
 use winit::event::*;
 use winit::event_loop::ActiveEventLoop;
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::keyboard::PhysicalKey;
 use winit::window::{Fullscreen, Window, WindowId};
 
 pub struct Application {
     graphics: Option<Graphics>,
     game: Game,
 
-    pressed_keys: HashSet<KeyCode>,
+    input: InputTracker,
+    #[allow(dead_code)] // Interpolation between ticks is not wired into rendering yet
     alpha: f64,
-    mouse_x: f64,
-    mouse_y: f64,
-    mouse_dx: f64,
-    mouse_dy: f64
 }
 
 pub struct Input {
-    pub pressed_keys: Vec<KeyCode>,
+    active: HashSet<Action>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+    #[allow(dead_code)] // Summed mouse movement, the camera only uses the deltas
     pub mouse_x: f64,
+    #[allow(dead_code)]
     pub mouse_y: f64,
     pub mouse_dx: f64,
     pub mouse_dy: f64,
+    #[allow(dead_code)] // Only the vertical wheel selects blocks so far
+    pub scroll_dx: f64, // In lines, positive is right
+    pub scroll_dy: f64, // In lines, positive is up
+    #[allow(dead_code)] // For text fields, the game has none yet
+    pub text: String, // Typed since the last tick, including key repeats
 }
 
-impl Application {
-    pub fn new() -> Self {
-        let game = Game::new();
+impl Input {
+    // Held down, or pressed and let go again since the last tick
+    pub fn is_active(&self, action: Action) -> bool {
+        self.active.contains(&action)
+    }
 
+    // Since the last tick, holding a binding down does not repeat this
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    // Since the last tick, and none of the action's bindings are held anymore
+    #[allow(dead_code)] // Part of the action queries next to just_pressed, no system waits for a release yet
+    pub fn just_released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+}
+
+#[cfg(test)]
+impl Input {
+    // A tick in which the actions were pressed and nothing else happened, for testing systems
+    pub fn pressing(actions: &[Action]) -> Self {
+        let actions = actions.iter().copied().collect::<HashSet<_>>();
         Self {
-            graphics: None,
-            pressed_keys: HashSet::new(),
-            game,
-            alpha: 0.5,
+            active: actions.clone(),
+            pressed: actions,
+            released: HashSet::new(),
             mouse_x: 0.,
             mouse_y: 0.,
             mouse_dx: 0.,
-            mouse_dy: 0.
+            mouse_dy: 0.,
+            scroll_dx: 0.,
+            scroll_dy: 0.,
+            text: String::new(),
         }
     }
+}
+
+// What the window reported since the last tick, turned into an Input through the bindings
+struct InputTracker {
+    bindings: Bindings,
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>, // Since the last tick, so presses shorter than a tick are not missed
+    released: HashSet<Binding>, // Since the last tick
+    mouse_x: f64,
+    mouse_y: f64,
+    mouse_dx: f64,
+    mouse_dy: f64,
+    scroll_dx: f64,
+    scroll_dy: f64,
+    text: String,
+}
 
-    pub fn get_input(&self) -> Input {
+impl InputTracker {
+    fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            mouse_x: 0.,
+            mouse_y: 0.,
+            mouse_dx: 0.,
+            mouse_dy: 0.,
+            scroll_dx: 0.,
+            scroll_dy: 0.,
+            text: String::new(),
+        }
+    }
+
+    // False for key repeats of a binding that is held already
+    fn press(&mut self, binding: Binding) -> bool {
+        if !self.held.insert(binding) {
+            return false;
+        }
+        self.pressed.insert(binding);
+        true
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.released.insert(binding);
+        }
+    }
+
+    fn get_input(&self) -> Input {
+        // Actions with any of their bindings in the set
+        let triggered = |bindings: &HashSet<Binding>| Action::ALL.into_iter()
+            .filter(|action| self.bindings.get(*action).iter().any(|b| bindings.contains(b)))
+            .collect::<HashSet<_>>();
+
+        let held = triggered(&self.held);
+        let pressed = triggered(&self.pressed);
         Input {
-            pressed_keys: self.pressed_keys.iter().copied().collect(),
+            active: held.union(&pressed).copied().collect(),
+            released: triggered(&self.released).difference(&held).copied().collect(),
+            pressed,
             mouse_x: self.mouse_x,
             mouse_y: self.mouse_y,
             mouse_dx: self.mouse_dx,
-            mouse_dy: self.mouse_dy
+            mouse_dy: self.mouse_dy,
+            scroll_dx: self.scroll_dx,
+            scroll_dy: self.scroll_dy,
+            text: self.text.clone(),
+        }
+    }
+
+    // Forgets what only lasts for one tick
+    fn end_tick(&mut self) {
+        self.mouse_dx = 0.;
+        self.mouse_dy = 0.;
+        self.scroll_dx = 0.;
+        self.scroll_dy = 0.;
+        self.text.clear();
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+impl Application {
+    // Scrolling by touchpad is in pixels, the game only knows lines
+    const SCROLL_PIXELS_PER_LINE: f64 = 20.;
+
+    pub fn new() -> Self {
+        let game = Game::new();
+
+        Self {
+            graphics: None,
+            input: InputTracker::new(Self::load_bindings()),
+            game,
+            alpha: 0.5,
+        }
+    }
+
+    fn load_bindings() -> Bindings {
+        match Bindings::load(Path::new(Bindings::DEFAULT_PATH)) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                log::warn!("Using builtin bindings: {:#}", e);
+                Bindings::builtin()
+            }
         }
     }
 
+    #[allow(dead_code)] // about_to_wait redraws every frame, this is for redraws outside the loop
     pub fn request_redraw(&self) {
-        match &self.graphics {
-            Some(graphics) => graphics.window.request_redraw(),
-            None => {}
+        if let Some(graphics) = &self.graphics {
+            graphics.window.request_redraw();
         }
     }
 
     pub fn on_ready(&mut self) {
         self.game.reset_deltatime(); // Reset to prevent deltatime accumulation during loading
     }
+
+    pub fn exit(&mut self, event_loop: &ActiveEventLoop) {
+        self.game.save();
+        event_loop.exit();
+    }
+
+    // Actions of the application itself happen right away, the game's wait for the next tick
+    fn press(&mut self, event_loop: &ActiveEventLoop, binding: Binding) {
+        if !self.input.press(binding) {
+            return;
+        }
+
+        if self.input.bindings.is_bound(Action::ReloadBindings, binding) {
+            match Bindings::load(Path::new(Bindings::DEFAULT_PATH)) {
+                Ok(bindings) => self.input.bindings = bindings,
+                Err(e) => log::error!("Keeping the current bindings: {:#}", e)
+            }
+        }
+        if self.input.bindings.is_bound(Action::Exit, binding) {
+            self.exit(event_loop);
+        }
+    }
 }
 
 impl ApplicationHandler<Graphics> for Application {
@@ -80,23 +227,18 @@ impl ApplicationHandler<Graphics> for Application {
             window.set_cursor_grab(winit::window::CursorGrabMode::Locked).unwrap();
             window.set_cursor_visible(false);
 
-            self.graphics = Some(pollster::block_on(Graphics::new(window)).unwrap());
+            self.graphics = Some(pollster::block_on(Graphics::new(window, self.game.registry())).unwrap());
             self.on_ready();
     }
 
-    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
         // Trigger rendering
-        match &self.graphics {
-            Some(graphics) => {
-                self.game.tick(self.get_input());  // Trigger game loop
-                graphics.window.request_redraw();
-            },
-            None => {}
+        if let Some(graphics) = &self.graphics {
+            self.game.tick(self.input.get_input());  // Trigger game loop
+            graphics.window.request_redraw();
         }
 
-        // Reset mouse dx and dy
-        self.mouse_dx = 0.;
-        self.mouse_dy = 0.;
+        self.input.end_tick();
     }
 
     fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: Graphics) {
@@ -104,21 +246,15 @@ impl ApplicationHandler<Graphics> for Application {
     }
 
     fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
-        match event {
-            DeviceEvent::MouseMotion { 
-                delta 
-            } => {
-                self.mouse_x += delta.0;
-                self.mouse_y += delta.1;
-                self.mouse_dx += delta.0;
-                self.mouse_dy += delta.1;
-            },
-            _ => {}
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.input.mouse_x += delta.0;
+            self.input.mouse_y += delta.1;
+            self.input.mouse_dx += delta.0;
+            self.input.mouse_dy += delta.1;
         }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
-        let input = self.get_input();
         let graphics = match &mut self.graphics {
             Some(graphics) => graphics,
             None => return,
@@ -126,7 +262,7 @@ impl ApplicationHandler<Graphics> for Application {
 
 
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => self.exit(event_loop),
             WindowEvent::Resized(size) => graphics.resize(size.width, size.height),
             WindowEvent::RedrawRequested => {
                 match graphics.render(&mut self.game.get_renderables()) {
@@ -140,27 +276,30 @@ impl ApplicationHandler<Graphics> for Application {
                     }
                 }
             },
-            WindowEvent::KeyboardInput { 
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(code),
-                        state: key_state,
-                        ..
-                    },
-                ..
-             } => {
-                if key_state == ElementState::Pressed {
-                    self.pressed_keys.insert(code);
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let (ElementState::Pressed, Some(text)) = (event.state, &event.text) {
+                    self.input.text.push_str(text);
                 }
-                if key_state == ElementState::Released {
-                    self.pressed_keys.remove(&code);
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => self.press(event_loop, Binding::Key(code)),
+                        ElementState::Released => self.input.release(Binding::Key(code))
+                    }
                 }
-
-                match code {
-                    KeyCode::Escape => {
-                        event_loop.exit()
-                    },
-                    _ => {}
+             },
+             WindowEvent::MouseWheel { delta, .. } => {
+                let (dx, dy) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+                    MouseScrollDelta::PixelDelta(position) => (position.x / Self::SCROLL_PIXELS_PER_LINE,
+                                                               position.y / Self::SCROLL_PIXELS_PER_LINE)
+                };
+                self.input.scroll_dx += dx;
+                self.input.scroll_dy += dy;
+             },
+             WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => self.press(event_loop, Binding::Mouse(button)),
+                    ElementState::Released => self.input.release(Binding::Mouse(button))
                 }
              },
              _ => {}
@@ -168,3 +307,132 @@ impl ApplicationHandler<Graphics> for Application {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::KeyCode;
+
+    use super::*;
+
+    const W: Binding = Binding::Key(KeyCode::KeyW);
+    const UP: Binding = Binding::Key(KeyCode::ArrowUp);
+    const SPACE: Binding = Binding::Key(KeyCode::Space);
+
+    fn tracker() -> InputTracker {
+        InputTracker::new(Bindings::parse("move_forward = KeyW ArrowUp\njump = Space\n").unwrap())
+    }
+
+    // The input a tick sees, and everything after it belongs to the next tick
+    fn tick(tracker: &mut InputTracker) -> Input {
+        let input = tracker.get_input();
+        tracker.end_tick();
+        input
+    }
+
+    #[test]
+    fn a_tap_within_one_tick_is_pressed_and_active() {
+        let mut tracker = tracker();
+        assert!(tracker.press(SPACE));
+        tracker.release(SPACE);
+
+        let input = tick(&mut tracker);
+        assert!(input.just_pressed(Action::Jump) && input.is_active(Action::Jump));
+
+        let input = tick(&mut tracker);
+        assert!(!input.just_pressed(Action::Jump) && !input.is_active(Action::Jump));
+    }
+
+    #[test]
+    fn holding_is_pressed_once_and_active_until_released() {
+        let mut tracker = tracker();
+        tracker.press(SPACE);
+        assert!(tick(&mut tracker).just_pressed(Action::Jump));
+
+        for _ in 0..3 {
+            let input = tick(&mut tracker);
+            assert!(input.is_active(Action::Jump) && !input.just_pressed(Action::Jump));
+        }
+
+        tracker.release(SPACE);
+        assert!(!tick(&mut tracker).is_active(Action::Jump));
+    }
+
+    #[test]
+    fn letting_go_is_released_once() {
+        let mut tracker = tracker();
+        tracker.press(SPACE);
+        tick(&mut tracker);
+        assert!(!tick(&mut tracker).just_released(Action::Jump));
+
+        tracker.release(SPACE);
+        let input = tick(&mut tracker);
+        assert!(input.just_released(Action::Jump) && !input.is_active(Action::Jump));
+        assert!(!tick(&mut tracker).just_released(Action::Jump));
+
+        // A tap within one tick is both
+        tracker.press(SPACE);
+        tracker.release(SPACE);
+        let input = tick(&mut tracker);
+        assert!(input.just_pressed(Action::Jump) && input.just_released(Action::Jump));
+    }
+
+    #[test]
+    fn key_repeats_are_not_presses() {
+        let mut tracker = tracker();
+        tracker.press(SPACE);
+        tick(&mut tracker);
+
+        assert!(!tracker.press(SPACE));
+        assert!(!tracker.press(SPACE));
+        let input = tick(&mut tracker);
+        assert!(input.is_active(Action::Jump) && !input.just_pressed(Action::Jump));
+
+        // Let go and pressed again within a tick is a new press
+        tracker.release(SPACE);
+        assert!(tracker.press(SPACE));
+        assert!(tick(&mut tracker).just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn an_action_stays_active_while_any_binding_is_held() {
+        let mut tracker = tracker();
+        tracker.press(W);
+        tick(&mut tracker);
+
+        tracker.press(UP);
+        tracker.release(W);
+        let input = tick(&mut tracker);
+        assert!(input.is_active(Action::MoveForward) && input.just_pressed(Action::MoveForward));
+        assert!(!input.is_active(Action::Jump));
+
+        assert!(!input.just_released(Action::MoveForward)); // ArrowUp still holds it
+
+        tracker.release(UP);
+        let input = tick(&mut tracker);
+        assert!(!input.is_active(Action::MoveForward) && input.just_released(Action::MoveForward));
+    }
+
+    #[test]
+    fn mouse_movement_and_scrolling_last_one_tick() {
+        let mut tracker = tracker();
+        tracker.mouse_x += 3.;
+        tracker.mouse_dx += 3.;
+        tracker.scroll_dx += 0.5;
+        tracker.scroll_dy += 1.;
+        tracker.scroll_dy -= 3.;
+
+        let input = tick(&mut tracker);
+        assert_eq!((input.mouse_dx, input.scroll_dx, input.scroll_dy), (3., 0.5, -2.));
+        let input = tick(&mut tracker);
+        assert_eq!((input.mouse_dx, input.scroll_dx, input.scroll_dy), (0., 0., 0.));
+        assert_eq!(input.mouse_x, 3.); // The position is kept
+    }
+
+    #[test]
+    fn typed_text_lasts_one_tick() {
+        let mut tracker = tracker();
+        tracker.text.push_str("ab");
+        tracker.text.push('c');
+        assert_eq!(tick(&mut tracker).text, "abc");
+        assert_eq!(tick(&mut tracker).text, "");
+    }
+}
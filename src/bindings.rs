@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ anyhow, bail, Context };
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+// Everything the player can do with a key or mouse button, systems ask the Input about these
+// instead of about keys so the keys can be changed
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Break,
+    Place,
+    ToggleMeshing,
+    ReloadBindings,
+    Exit,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [Self::MoveForward, Self::MoveBackward, Self::MoveLeft, Self::MoveRight, Self::Jump,
+                                   Self::Break, Self::Place, Self::ToggleMeshing, Self::ReloadBindings, Self::Exit];
+
+    // As written in the bindings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MoveForward => "move_forward",
+            Self::MoveBackward => "move_backward",
+            Self::MoveLeft => "move_left",
+            Self::MoveRight => "move_right",
+            Self::Jump => "jump",
+            Self::Break => "break",
+            Self::Place => "place",
+            Self::ToggleMeshing => "toggle_meshing",
+            Self::ReloadBindings => "reload_bindings",
+            Self::Exit => "exit"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// Pairs every name usable in the bindings file with its key, names are those of winit's KeyCode
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, KeyCode)] = &[$((stringify!($key), KeyCode::$key)),*];
+    };
+}
+
+key_names!(
+    KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM,
+    KeyN, KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    ArrowUp, ArrowDown, ArrowLeft, ArrowRight,
+    Space, Enter, Tab, Escape, Backspace, Delete, Insert, Home, End, PageUp, PageDown,
+    ShiftLeft, ShiftRight, ControlLeft, ControlRight, AltLeft, AltRight, CapsLock,
+    Minus, Equal, BracketLeft, BracketRight, Backslash, Semicolon, Quote, Backquote, Comma, Period, Slash,
+);
+
+const MOUSE_NAMES: &[(&str, MouseButton)] = &[
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+    ("MouseBack", MouseButton::Back),
+    ("MouseForward", MouseButton::Forward),
+];
+
+impl Binding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEY_NAMES.iter().find(|(n, _)| *n == name).map(|(_, key)| Self::Key(*key))
+            .or_else(|| MOUSE_NAMES.iter().find(|(n, _)| *n == name).map(|(_, button)| Self::Mouse(*button)))
+    }
+}
+
+// Which keys and mouse buttons trigger which actions. A binding may trigger several actions and an
+// action may have several bindings, any one of them is enough.
+pub struct Bindings {
+    actions: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub const DEFAULT_PATH: &'static str = "resources/bindings.txt";
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read bindings {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid bindings {}", path.display()))
+    }
+
+    // Bindings compiled into the executable, used when the data file cannot be read
+    pub fn builtin() -> Self {
+        Self::parse(include_str!("../resources/bindings.txt")).expect("Builtin bindings are invalid")
+    }
+
+    // Actions left out of the text have no bindings
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut bindings = Self { actions: HashMap::new() };
+
+        for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or(anyhow!("Line {}: expected action = bindings", number))?;
+            let action = Action::from_name(name.trim()).ok_or(anyhow!("Line {}: unknown action {}", number, name.trim()))?;
+            if bindings.actions.contains_key(&action) {
+                bail!("Line {}: action {} is bound twice", number, action.name());
+            }
+
+            bindings.actions.insert(action, Vec::new());
+            for binding in value.split_whitespace() {
+                let binding = Binding::from_name(binding).ok_or(anyhow!("Line {}: unknown key or button {}", number, binding))?;
+                bindings.bind(action, binding);
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bound = self.actions.entry(action).or_default();
+        if !bound.contains(&binding) {
+            bound.push(binding);
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_bound(&self, action: Action, binding: Binding) -> bool {
+        self.get(action).contains(&binding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_comments_and_several_bindings_per_action() {
+        let bindings = Bindings::parse("# Movement\n\n  jump = Space MouseMiddle  \nbreak=MouseLeft\n# exit = Escape\nplace =\n").unwrap();
+        assert_eq!(bindings.get(Action::Jump), &[Binding::Key(KeyCode::Space), Binding::Mouse(MouseButton::Middle)]);
+        assert_eq!(bindings.get(Action::Break), &[Binding::Mouse(MouseButton::Left)]);
+        assert!(bindings.get(Action::Place).is_empty());
+        assert!(bindings.get(Action::Exit).is_empty());
+    }
+
+    #[test]
+    fn a_binding_may_trigger_several_actions() {
+        let bindings = Bindings::parse("jump = Space KeyJ\nplace = KeyJ\n").unwrap();
+        assert!(bindings.is_bound(Action::Jump, Binding::Key(KeyCode::KeyJ)));
+        assert!(bindings.is_bound(Action::Place, Binding::Key(KeyCode::KeyJ)));
+        assert!(!bindings.is_bound(Action::Break, Binding::Key(KeyCode::KeyJ)));
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_duplicate_actions() {
+        for text in ["fly = Space", "jump = Spacebar", "jump = MouseLeft Mouse6", "jump = Space\njump = KeyJ", "jump Space"] {
+            assert!(Bindings::parse(text).is_err(), "{}", text);
+        }
+
+        let error = Bindings::parse("jump = Space\n\nbreak = Key1").err().unwrap();
+        assert!(error.to_string().contains("Line 3"), "{}", error);
+    }
+
+    #[test]
+    fn builtin_bindings_cover_every_action() {
+        let bindings = Bindings::builtin();
+        for action in Action::ALL {
+            assert!(!bindings.get(action).is_empty(), "{}", action.name());
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+    }
+}
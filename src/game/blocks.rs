@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ anyhow, bail, Context };
+
+use super::units::BlockID;
+
+// Order matches the face indices of the cube vertices
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum BlockFace {
+    Front,
+    Right,
+    Back,
+    Left,
+    Top,
+    Bottom,
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [Self::Front, Self::Right, Self::Back, Self::Left, Self::Top, Self::Bottom];
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockDefinition {
+    pub id: BlockID,
+    pub name: String,
+    pub solid: bool,
+    pub transparent: bool,
+    pub textures: [String; 6], // Indexed by BlockFace
+    pub light_emission: u8,
+    pub hardness: f32,
+}
+
+impl BlockDefinition {
+    fn new(name: &str) -> Self {
+        Self {
+            id: 0,
+            name: name.to_owned(),
+            solid: true,
+            transparent: false,
+            textures: Default::default(),
+            light_emission: 0,
+            hardness: 1.,
+        }
+    }
+
+    pub fn texture(&self, face: BlockFace) -> &str {
+        &self.textures[face as usize]
+    }
+
+    // Whether the block has any faces to draw at all
+    pub fn is_visible(&self) -> bool {
+        self.textures.iter().any(|t| !t.is_empty())
+    }
+
+    pub fn is_breakable(&self) -> bool {
+        self.hardness >= 0.
+    }
+}
+
+pub struct BlockRegistry {
+    definitions: Vec<Option<BlockDefinition>>, // Indexed by id
+    names: HashMap<String, BlockID>,
+}
+
+impl BlockRegistry {
+    // Air is always id 0, empty space in the world is stored as this
+    pub const AIR: BlockID = 0;
+    pub const DEFAULT_PATH: &'static str = "resources/blocks.txt";
+    pub const MAX_LIGHT: u8 = 15;
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read block definitions {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid block definitions {}", path.display()))
+    }
+
+    // Definitions compiled into the executable, used when the data file cannot be read
+    pub fn builtin() -> Self {
+        Self::parse(include_str!("../../resources/blocks.txt")).expect("Builtin block definitions are invalid")
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut parsed: Vec<(usize, BlockDefinition)> = Vec::new();
+        let mut has_id = Vec::new();
+
+        for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                parsed.push((number, BlockDefinition::new(name.trim())));
+                has_id.push(false);
+                continue;
+            }
+
+            let definition = match parsed.last_mut() {
+                Some((_, definition)) => definition,
+                None => bail!("Line {}: property outside of a block", number)
+            };
+            let (key, value) = line.split_once('=').ok_or(anyhow!("Line {}: expected key = value", number))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "id" => {
+                    definition.id = value.parse().with_context(|| format!("Line {}: invalid id", number))?;
+                    *has_id.last_mut().unwrap() = true;
+                },
+                "solid" => definition.solid = value.parse().with_context(|| format!("Line {}: invalid bool", number))?,
+                "transparent" => definition.transparent = value.parse().with_context(|| format!("Line {}: invalid bool", number))?,
+                "light" => {
+                    definition.light_emission = value.parse().with_context(|| format!("Line {}: invalid light", number))?;
+                    if definition.light_emission > Self::MAX_LIGHT {
+                        bail!("Line {}: light must be between 0 and {}", number, Self::MAX_LIGHT);
+                    }
+                },
+                "hardness" => definition.hardness = value.parse().with_context(|| format!("Line {}: invalid hardness", number))?,
+                "textures" => definition.textures = Self::parse_textures(value)
+                    .ok_or(anyhow!("Line {}: expected 1, 3 or 6 textures", number))?,
+                _ => bail!("Line {}: unknown property {}", number, key)
+            }
+        }
+
+        let mut registry = Self {
+            definitions: vec![None; BlockID::MAX as usize + 1],
+            names: HashMap::new(),
+        };
+        for ((number, definition), has_id) in parsed.into_iter().zip(has_id) {
+            if !has_id {
+                bail!("Block {} on line {} has no id", definition.name, number);
+            }
+            if registry.names.contains_key(&definition.name) {
+                bail!("Block {} on line {} is defined twice", definition.name, number);
+            }
+            if let Some(existing) = &registry.definitions[definition.id as usize] {
+                bail!("Blocks {} and {} share id {}", existing.name, definition.name, definition.id);
+            }
+
+            registry.names.insert(definition.name.clone(), definition.id);
+            let id = definition.id as usize;
+            registry.definitions[id] = Some(definition);
+        }
+
+        match registry.get(Self::AIR) {
+            Some(air) if !air.solid && air.transparent && !air.is_visible() => {},
+            _ => bail!("Block id {} must be an invisible, non solid, transparent air block", Self::AIR)
+        }
+
+        Ok(registry)
+    }
+
+    fn parse_textures(value: &str) -> Option<[String; 6]> {
+        let names = value.split_whitespace().map(|s| s.to_owned()).collect::<Vec<_>>();
+        match names.as_slice() {
+            [all] => Some(std::array::from_fn(|_| all.clone())),
+            [top, side, bottom] => Some([side.clone(), side.clone(), side.clone(), side.clone(), top.clone(), bottom.clone()]),
+            [_, _, _, _, _, _] => Some(names.try_into().unwrap()),
+            _ => None
+        }
+    }
+
+    pub fn get(&self, id: BlockID) -> Option<&BlockDefinition> {
+        self.definitions[id as usize].as_ref()
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<BlockID> {
+        self.names.get(name).copied()
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.iter().flatten()
+    }
+
+    // Unknown ids, like those from a newer save, are treated as solid so nothing falls through them
+    pub fn is_solid(&self, id: BlockID) -> bool {
+        self.get(id).map(|d| d.solid).unwrap_or(true)
+    }
+
+    // Placing a block may replace these, air and fluids have nothing to place against or to keep
+    pub fn is_replaceable(&self, id: BlockID) -> bool {
+        !self.is_solid(id)
+    }
+
+    pub fn is_transparent(&self, id: BlockID) -> bool {
+        self.get(id).map(|d| d.transparent).unwrap_or(false)
+    }
+
+    pub fn is_visible(&self, id: BlockID) -> bool {
+        self.get(id).map(|d| d.is_visible()).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIR: &str = "[air]\nid = 0\nsolid = false\ntransparent = true\n";
+
+    fn error(text: &str) -> String {
+        match BlockRegistry::parse(text) {
+            Ok(_) => panic!("Parsed invalid definitions"),
+            Err(e) => format!("{:#}", e)
+        }
+    }
+
+    #[test]
+    fn parses_definitions() {
+        let text = format!("# Comment\n{}\n[glass]\nid = 3\ntransparent = true\ntextures = glass\n\n\
+                            [lamp]\nid = 7\ntextures = top side bottom\nlight = 15\nhardness = -1\n", AIR);
+        let registry = BlockRegistry::parse(&text).unwrap();
+
+        let glass = registry.get(registry.id_of("glass").unwrap()).unwrap();
+        assert_eq!(glass.id, 3);
+        assert!(glass.solid && glass.transparent && glass.is_breakable());
+        assert!(BlockFace::ALL.iter().all(|face| glass.texture(*face) == "glass"));
+
+        let lamp = registry.get(7).unwrap();
+        assert_eq!((lamp.name.as_str(), lamp.light_emission), ("lamp", 15));
+        assert_eq!((lamp.texture(BlockFace::Top), lamp.texture(BlockFace::Left), lamp.texture(BlockFace::Bottom)),
+                   ("top", "side", "bottom"));
+        assert!(!lamp.is_breakable());
+        assert_eq!(registry.definitions().count(), 3);
+        assert!(registry.get(5).is_none() && registry.is_solid(5));
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let message = error(&format!("{}[stone]\nid = 1\n[stone]\nid = 2\n", AIR));
+        assert!(message.contains("stone on line 7 is defined twice"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_unknown_properties() {
+        let message = error(&format!("{}[stone]\nid = 1\nshiny = true\n", AIR));
+        assert!(message.contains("Line 7: unknown property shiny"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_light_outside_the_range() {
+        let message = error(&format!("{}[lamp]\nid = 1\nlight = 16\n", AIR));
+        assert!(message.contains("Line 7: light must be between 0 and 15"), "{}", message);
+
+        let message = error(&format!("{}[lamp]\nid = 1\nlight = -1\n", AIR));
+        assert!(message.contains("Line 7: invalid light"), "{}", message);
+    }
+}
@@ -0,0 +1,154 @@
+use cgmath::Vector3;
+use legion::{ World, IntoQuery };
+
+use crate::application::Input;
+use crate::bindings::Action;
+use super::super::blocks::BlockRegistry;
+use super::super::generation::worldblocks::WorldBlocks;
+use super::super::units::{ BlockCoords, BlockID };
+use super::collision::{ BoxCollider, CollidesWithBlocks };
+use super::spatial::{ Direction, Position };
+
+// Breaks the targeted block or places the selected block against it
+pub struct BlockEditor {
+    pub reach: f32,
+    pub selected: BlockID,
+}
+
+pub fn edit_blocks(world: &mut World, blocks: &mut WorldBlocks, input: &Input) {
+    if input.scroll_dy != 0. {
+        select_blocks(world, blocks.registry(), input.scroll_dy);
+    }
+
+    let (breaking, placing) = (input.just_pressed(Action::Break), input.just_pressed(Action::Place));
+    if !breaking && !placing {
+        return;
+    }
+
+    let mut query = <(&BlockEditor, &Position, &Direction)>::query();
+    let edits = query.iter(world)
+        .filter_map(|(editor, pos, dir)| blocks.raycast(pos, dir, editor.reach).map(|hit| (editor.selected, hit)))
+        .collect::<Vec<_>>();
+
+    for (selected, hit) in edits {
+        if breaking {
+            if blocks.registry().get(hit.id).is_some_and(|d| d.is_breakable()) {
+                blocks.set_block(hit.block, BlockRegistry::AIR);
+            }
+        } else if blocks.get_block(hit.adjacent).is_some_and(|id| blocks.registry().is_replaceable(id))
+            && !overlaps_collider(world, hit.adjacent) {
+            blocks.set_block(hit.adjacent, selected);
+        }
+    }
+}
+
+// Steps every editor's selection through the placeable blocks, one per tick whatever the scroll amount
+fn select_blocks(world: &mut World, registry: &BlockRegistry, scroll: f64) {
+    let placeable = registry.definitions()
+        .filter(|d| d.id != BlockRegistry::AIR && d.is_visible() && d.is_breakable())
+        .map(|d| d.id)
+        .collect::<Vec<_>>();
+    if placeable.is_empty() {
+        return;
+    }
+
+    for editor in <&mut BlockEditor>::query().iter_mut(world) {
+        let current = placeable.iter().position(|id| *id == editor.selected).unwrap_or(0);
+        let next = if scroll > 0. { current + placeable.len() - 1 } else { current + 1 };
+        editor.selected = placeable[next % placeable.len()];
+    }
+}
+
+// Whether a block at the position would end up inside anything that collides with blocks
+fn overlaps_collider(world: &World, block: BlockCoords) -> bool {
+    let (low, high) = (block.cast::<f32>().unwrap(), block.cast::<f32>().unwrap() + Vector3::new(1., 1., 1.));
+    let mut query = <(&BoxCollider, &CollidesWithBlocks, &Position)>::query();
+    query.iter(world).any(|(collider, _, pos)| {
+        let (collider_low, collider_high) = (pos.vector - collider.bounds, pos.vector + collider.bounds);
+        (0..3).all(|axis| collider_low[axis] < high[axis] && collider_high[axis] > low[axis])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cgmath::Point3;
+
+    use super::*;
+    use super::super::super::generation::{ slice::Slice, stack::Stack };
+    use super::super::super::units::StackCoords;
+
+    const TARGET: BlockCoords = BlockCoords { x: 0, y: 1, z: 0 };
+
+    // A stone floor at y=0 and an editor above it looking straight down, so placing targets y=1
+    fn setup() -> (World, WorldBlocks) {
+        let mut blocks = WorldBlocks::new(Arc::new(BlockRegistry::builtin()));
+        let mut stack = Stack::new();
+        stack.insert_slice(0, Slice::new(blocks.registry().id_of("stone").unwrap()));
+        blocks.insert_stack(StackCoords { x: 0, z: 0 }, stack);
+
+        let mut world = World::default();
+        world.push((
+            BlockEditor { reach: 6., selected: blocks.registry().id_of("planks").unwrap() },
+            Position { vector: Point3::new(0.5, 4.5, 0.5) },
+            Direction { vector: Vector3::new(0., -1., 0.) },
+        ));
+        (world, blocks)
+    }
+
+    fn place(world: &mut World, blocks: &mut WorldBlocks) {
+        edit_blocks(world, blocks, &Input::pressing(&[Action::Place]));
+    }
+
+    #[test]
+    fn places_against_the_targeted_face() {
+        let (mut world, mut blocks) = setup();
+        place(&mut world, &mut blocks);
+        assert_eq!(blocks.get_block(TARGET), blocks.registry().id_of("planks"));
+    }
+
+    #[test]
+    fn does_not_place_inside_a_collider() {
+        let (mut world, mut blocks) = setup();
+        world.push((
+            BoxCollider { bounds: Vector3::new(0.4, 1., 0.4) },
+            CollidesWithBlocks,
+            Position { vector: Point3::new(0.5, 2., 0.5) }, // Standing on the floor
+        ));
+
+        place(&mut world, &mut blocks);
+        assert_eq!(blocks.get_block(TARGET), Some(BlockRegistry::AIR));
+    }
+
+    #[test]
+    fn places_into_water() {
+        let (mut world, mut blocks) = setup();
+        let water = blocks.registry().id_of("water").unwrap();
+        for y in 1..3 {
+            blocks.set_block(BlockCoords::new(0, y, 0), water);
+        }
+
+        // The ray passes through the water to the floor
+        place(&mut world, &mut blocks);
+        assert_eq!(blocks.get_block(TARGET), blocks.registry().id_of("planks"));
+        assert_eq!(blocks.get_block(TARGET + Vector3::unit_y()), Some(water));
+    }
+
+    #[test]
+    fn does_not_replace_solid_blocks() {
+        let (mut world, mut blocks) = setup();
+        let stone = blocks.registry().id_of("stone").unwrap();
+        assert!(!blocks.registry().is_replaceable(stone));
+        assert!(blocks.registry().is_replaceable(BlockRegistry::AIR));
+
+        // Starting inside the floor the ray hits it with no face, so the target is the floor itself
+        world.push((
+            BlockEditor { reach: 6., selected: blocks.registry().id_of("planks").unwrap() },
+            Position { vector: Point3::new(0.5, 0.5, 0.5) },
+            Direction { vector: Vector3::new(0., -1., 0.) },
+        ));
+        place(&mut world, &mut blocks);
+        assert_eq!(blocks.get_block(BlockCoords::new(0, 0, 0)), Some(stone));
+    }
+}
@@ -0,0 +1,26 @@
+use std::collections::HashSet;
+
+use super::super::coords;
+use super::super::units::StackCoords;
+use super::spatial::Position;
+
+// Keeps the world loaded around an entity, radii are in stacks
+//  load_radius: stacks are loaded or generated and kept in memory
+//  sim_radius: stacks are needed right away, entities in them are simulated
+#[derive(Copy, Clone, Debug)]
+pub struct ChunkLoader {
+    pub load_radius: i32,
+    pub sim_radius: i32,
+}
+
+// Resource holding every stack within the simulation radius of a loader
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedArea {
+    pub stacks: HashSet<StackCoords>,
+}
+
+impl SimulatedArea {
+    pub fn contains(&self, position: &Position) -> bool {
+        self.stacks.contains(&coords::world_to_stack(position.vector))
+    }
+}
@@ -1,5 +1,5 @@
 use cgmath::Vector3;
-use legion::{ system, World, systems::Builder, IntoQuery };
+use legion::{ World, IntoQuery };
 
 use super::super::generation::worldblocks::WorldBlocks;
 
@@ -11,11 +11,27 @@ pub struct BoxCollider {
 
 pub struct CollidesWithBlocks;
 
-pub fn block_collide(world: &mut World, blocks: &WorldBlocks) {
-    let mut query = <(&BoxCollider, &CollidesWithBlocks, &mut Position, &mut Velocity)>::query();
+// Whether falling was stopped by a block in the last collision pass
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OnGround {
+    pub grounded: bool
+}
+
+pub fn block_collide(world: &mut World, blocks: &mut WorldBlocks) {
+    let mut query = <(&BoxCollider, &CollidesWithBlocks, &mut Position, &mut Velocity, Option<&mut OnGround>)>::query();
+
+    for (collider, _, pos, vel, mut ground) in query.iter_mut(world) {
+        if let Some(ground) = ground.as_deref_mut() {
+            ground.grounded = false;
+        }
 
-    for (collider, _, pos, vel) in query.iter_mut(world) {
         for (_id, dir, diff) in blocks.get_block_contact(collider, pos) {
+            if vel.vector.y < 0. && dir.y < 0 {
+                if let Some(ground) = ground.as_deref_mut() {
+                    ground.grounded = true;
+                }
+            }
+
             for (pos, vel, dir) in [(&mut pos.vector.x, &mut vel.vector.x, dir.x),
                               (&mut pos.vector.y, &mut vel.vector.y, dir.y),
                               (&mut pos.vector.z, &mut vel.vector.z, dir.z)] {
@@ -1,15 +1,35 @@
-use winit::keyboard::KeyCode;
 use legion::{ system, systems::Builder };
 use cgmath::{ Vector3, InnerSpace, Zero };
 
 use crate::game::components::spatial::Velocity;
-use crate::{application::Input, util::lerp};
+use crate::{application::Input, bindings::Action, util::lerp};
 use super::spatial::{ Position, Direction };
+use super::collision::OnGround;
 use super::time::Time;
 
 pub struct HumanoidKeyboardMovement {
     pub speed: f32,
     pub jump_vel: f32,
+    pub coyote_time: f32, // Seconds after leaving the ground that jumping still works
+    pub jump_buffer: f32, // Seconds a jump pressed in the air is kept for landing
+    airborne: f32, // Seconds since last on the ground
+    buffered_jump: Option<f32>, // Seconds since jump was pressed, until it is used or too old
+}
+
+impl HumanoidKeyboardMovement {
+    pub const DEFAULT_COYOTE_TIME: f32 = 0.1;
+    pub const DEFAULT_JUMP_BUFFER: f32 = 0.15;
+
+    pub fn base(speed: f32, jump_vel: f32) -> Self {
+        Self {
+            speed,
+            jump_vel,
+            coyote_time: Self::DEFAULT_COYOTE_TIME,
+            jump_buffer: Self::DEFAULT_JUMP_BUFFER,
+            airborne: 0.,
+            buffered_jump: None,
+        }
+    }
 }
 
 pub struct MouseLook {
@@ -28,25 +48,23 @@ impl MouseLook {
 }
 
 #[system(for_each)]
-fn player_movement(movement: &HumanoidKeyboardMovement, dir: &Direction, pos: &mut Position, vel: &mut Velocity,
-     #[resource] input: &Input, #[resource] time: &Time) {
+fn player_movement(movement: &mut HumanoidKeyboardMovement, ground: &OnGround, dir: &Direction, pos: &mut Position,
+     vel: &mut Velocity, #[resource] input: &Input, #[resource] time: &Time) {
     let mut movement_vec = Vector3::new(0.0, 0.0, 0.0);
 
-    if input.pressed_keys.contains(&KeyCode::KeyW) || input.pressed_keys.contains(&KeyCode::ArrowUp) {
+    if input.is_active(Action::MoveForward) {
         movement_vec += dir.vector;
     }
-    if input.pressed_keys.contains(&KeyCode::KeyS) || input.pressed_keys.contains(&KeyCode::ArrowDown) {
+    if input.is_active(Action::MoveBackward) {
         movement_vec -= dir.vector;
     }
-    if input.pressed_keys.contains(&KeyCode::KeyD) || input.pressed_keys.contains(&KeyCode::ArrowRight) {
+    if input.is_active(Action::MoveRight) {
         movement_vec += dir.vector.cross(Vector3::unit_y());
     }
-    if input.pressed_keys.contains(&KeyCode::KeyA) || input.pressed_keys.contains(&KeyCode::ArrowLeft) {
+    if input.is_active(Action::MoveLeft) {
         movement_vec -= dir.vector.cross(Vector3::unit_y());
     }
-    if input.pressed_keys.contains(&KeyCode::Space) {
-        vel.vector.y += movement.jump_vel;
-    }
+    jump(movement, ground, vel, input, time);
 
     movement_vec.y = 0.;
     if movement_vec != Vector3::zero() { // If movement_vec is 0, normalize will return NaNs
@@ -54,16 +72,31 @@ fn player_movement(movement: &HumanoidKeyboardMovement, dir: &Direction, pos: &m
     }
 }
 
+// Jumps once per press, slightly before landing and slightly after walking off an edge still count
+fn jump(movement: &mut HumanoidKeyboardMovement, ground: &OnGround, vel: &mut Velocity, input: &Input, time: &Time) {
+    movement.airborne = if ground.grounded { 0. } else { movement.airborne + time.dt };
+    movement.buffered_jump = if input.just_pressed(Action::Jump) {
+        Some(0.)
+    } else {
+        movement.buffered_jump.map(|t| t + time.dt).filter(|t| *t <= movement.jump_buffer)
+    };
+
+    if movement.buffered_jump.is_some() && movement.airborne <= movement.coyote_time {
+        vel.vector.y = movement.jump_vel;
+        movement.buffered_jump = None;
+        movement.airborne = f32::INFINITY; // No second jump until landing
+    }
+}
+
 #[system(for_each)]
 fn look_around(look: &mut MouseLook, dir: &mut Direction, #[resource] input: &Input, #[resource] time: &Time) {
     look.true_dx = lerp(input.mouse_dx as f32, look.true_dx, look.alpha) * look.sensitivity * time.dt;
     look.true_dy = lerp(input.mouse_dy as f32, look.true_dy, look.alpha) * look.sensitivity * time.dt;
 
-    look.yaw += look.true_dx as f32 * look.sensitivity;
-    look.pitch += -look.true_dy as f32 * look.sensitivity;
+    look.yaw += look.true_dx * look.sensitivity;
+    look.pitch += -look.true_dy * look.sensitivity;
 
-    if look.pitch > 89.0 { look.pitch = 89.0 }
-    if look.pitch < -89.0 { look.pitch = -89.0 }
+    look.pitch = look.pitch.clamp(-89.0, 89.0);
 
     let xdir = look.yaw.to_radians().cos() * look.pitch.to_radians().cos();
     let ydir = look.pitch.to_radians().sin();
@@ -75,4 +108,82 @@ fn look_around(look: &mut MouseLook, dir: &mut Direction, #[resource] input: &In
 pub fn schedule(scheduler: &mut Builder) {
     scheduler.add_system(player_movement_system());
     scheduler.add_system(look_around_system());
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Time = Time { dt: 0.05 };
+
+    struct Jumper {
+        movement: HumanoidKeyboardMovement,
+        velocity: Velocity,
+    }
+
+    impl Jumper {
+        fn new() -> Self {
+            Self { movement: HumanoidKeyboardMovement::base(0.1, 2.), velocity: Velocity::zero() }
+        }
+
+        // In the air for longer than the coyote time
+        fn falling() -> Self {
+            let mut jumper = Self::new();
+            for _ in 0..3 {
+                jumper.tick(false, false);
+            }
+            jumper
+        }
+
+        // Runs a tick and returns whether it jumped
+        fn tick(&mut self, grounded: bool, pressed: bool) -> bool {
+            let input = Input::pressing(if pressed { &[Action::Jump] } else { &[] });
+            self.velocity = Velocity::zero();
+            jump(&mut self.movement, &OnGround { grounded }, &mut self.velocity, &input, &TICK);
+            self.velocity.vector.y == self.movement.jump_vel
+        }
+    }
+
+    #[test]
+    fn jumps_once_per_press_on_the_ground() {
+        let mut jumper = Jumper::new();
+        assert!(!jumper.tick(true, false));
+        assert!(jumper.tick(true, true));
+        assert!(!jumper.tick(false, false));
+        assert!(!jumper.tick(false, true)); // No second jump in the air
+    }
+
+    #[test]
+    fn jumps_shortly_after_leaving_the_ground() {
+        let mut jumper = Jumper::new();
+        jumper.tick(true, false);
+        assert!(jumper.tick(false, true)); // Walked off an edge 0.05s ago, within the coyote time
+    }
+
+    #[test]
+    fn jumps_on_landing_when_pressed_just_before() {
+        let mut jumper = Jumper::falling();
+        assert!(!jumper.tick(false, true));
+        assert!(!jumper.tick(false, false));
+        assert!(jumper.tick(true, false)); // 0.1s after the press, within the buffer
+    }
+
+    #[test]
+    fn late_jumps_do_not_fire() {
+        // Pressed too long after leaving the ground
+        let mut jumper = Jumper::new();
+        jumper.tick(true, false);
+        for _ in 0..3 {
+            jumper.tick(false, false);
+        }
+        assert!(!jumper.tick(false, true)); // 0.2s after, past the coyote time
+
+        // Pressed too long before landing
+        let mut jumper = Jumper::falling();
+        assert!(!jumper.tick(false, true));
+        for _ in 0..3 {
+            jumper.tick(false, false);
+        }
+        assert!(!jumper.tick(true, false)); // 0.2s after, past the buffer
+        assert!(!jumper.tick(true, false));
+    }
+}
@@ -2,10 +2,13 @@ pub mod spatial;
 pub mod time;
 pub mod input;
 pub mod collision;
+pub mod chunk_loader;
+pub mod block_edit;
 
 use spatial::*;
 use collision::*;
 
+#[allow(dead_code)] // Not spawned as one component yet
 pub struct TotalPhysics {
     position: Position,
     velocity: Velocity,
@@ -1,4 +1,5 @@
 use super::time::Time;
+use super::chunk_loader::SimulatedArea;
 
 use cgmath::{ Vector3, Point3, Zero };
 use legion::{ system, systems::Builder } ;
@@ -9,8 +10,9 @@ pub struct Position {
 }
 
 impl Position {
+    #[allow(dead_code)] // Entities spawn at a given position so far
     pub fn zero() -> Self {
-        return Self { vector: Point3{x: 0., y: 0., z: 0.} }
+        Self { vector: Point3{x: 0., y: 0., z: 0.} }
     }
 }
 
@@ -21,7 +23,7 @@ pub struct Velocity {
 
 impl Velocity {
     pub fn zero() -> Self {
-        return Self { vector: Vector3::zero() }
+        Self { vector: Vector3::zero() }
     }
 }
 
@@ -32,21 +34,26 @@ pub struct Direction {
 
 impl Direction {
     pub fn zero() -> Self {
-        return Self { vector: Vector3::zero() }
+        Self { vector: Vector3::zero() }
     }
 }
 
 pub struct Gravity;
 
+// Entities outside of the simulated area are frozen until a loader comes close again
 #[system(for_each)]
-fn apply_velocity(pos: &mut Position, vel: &Velocity, #[resource] time: &Time ) {
-    pos.vector += vel.vector * time.dt;
+fn apply_velocity(pos: &mut Position, vel: &Velocity, #[resource] time: &Time, #[resource] area: &SimulatedArea) {
+    if area.contains(pos) {
+        pos.vector += vel.vector * time.dt;
+    }
 }
 
 const GRAVITY: f32 = 9.8;
 #[system(for_each)]
-fn apply_gravity(vel: &mut Velocity, _grav: &Gravity, #[resource] time: &Time) {
-    vel.vector.y -= GRAVITY * time.dt;
+fn apply_gravity(vel: &mut Velocity, pos: &Position, _grav: &Gravity, #[resource] time: &Time, #[resource] area: &SimulatedArea) {
+    if area.contains(pos) {
+        vel.vector.y -= GRAVITY * time.dt;
+    }
 }
 
 pub fn schedule(scheduler: &mut Builder) {
@@ -0,0 +1,142 @@
+// Conversions between the coordinate spaces of the world
+//  World: continuous entity positions, a block occupies [n, n + 1) on every axis
+//  Block: integer position of a block in the world
+//  Stack: which Stack column a block belongs to
+//  Local: integer position of a block within its stack, x and z in [0, Slice::X_SIZE/Z_SIZE), y untouched
+//  Slice index: position of a local block inside a slice's block array
+//
+// Everything uses floor/euclidean division so that negative coordinates map the same way as positive ones,
+// block -1 is the last block of stack -1 rather than block -1 of stack 0.
+
+use super::generation::slice::Slice;
+use super::generation::stack::Stack;
+use super::units::{ BlockCoords, StackCoords, WorldCoords };
+
+pub fn world_to_block(position: WorldCoords) -> BlockCoords {
+    position.map(|c| c.floor() as i32)
+}
+
+pub fn world_to_stack(position: WorldCoords) -> StackCoords {
+    block_to_stack(world_to_block(position))
+}
+
+pub fn block_to_stack(position: BlockCoords) -> StackCoords {
+    StackCoords {
+        x: position.x.div_euclid(Slice::X_SIZE),
+        z: position.z.div_euclid(Slice::Z_SIZE),
+    }
+}
+
+pub fn block_to_local(position: BlockCoords) -> BlockCoords {
+    BlockCoords {
+        x: position.x.rem_euclid(Slice::X_SIZE),
+        y: position.y,
+        z: position.z.rem_euclid(Slice::Z_SIZE),
+    }
+}
+
+// Stack coordinates and the local position within that stack
+pub fn split_block(position: BlockCoords) -> (StackCoords, BlockCoords) {
+    (block_to_stack(position), block_to_local(position))
+}
+
+// World block position of the stack's (0, 0, 0) corner
+pub fn stack_origin(coords: StackCoords) -> BlockCoords {
+    BlockCoords {
+        x: coords.x * Slice::X_SIZE,
+        y: 0,
+        z: coords.z * Slice::Z_SIZE,
+    }
+}
+
+// World space corners of the box every block of the stack lies in
+pub fn stack_bounds(coords: StackCoords) -> (WorldCoords, WorldCoords) {
+    let origin = stack_origin(coords);
+    let min = WorldCoords::new(origin.x as f32, Stack::MIN_HEIGHT as f32, origin.z as f32);
+    let height = (Stack::MAX_HEIGHT - Stack::MIN_HEIGHT) as f32;
+    (min, min + cgmath::Vector3::new(Slice::X_SIZE as f32, height, Slice::Z_SIZE as f32))
+}
+
+pub fn local_to_block(coords: StackCoords, local: BlockCoords) -> BlockCoords {
+    let origin = stack_origin(coords);
+    BlockCoords {
+        x: origin.x + local.x,
+        y: local.y,
+        z: origin.z + local.z,
+    }
+}
+
+// Increases x, then z
+// Every z contains Slice::X_SIZE number of x values
+pub fn local_to_slice_index(local: BlockCoords) -> usize {
+    debug_assert!((0..Slice::X_SIZE).contains(&local.x) && (0..Slice::Z_SIZE).contains(&local.z),
+        "{:?} is not a local coordinate", local);
+    (local.x + local.z * Slice::X_SIZE) as usize
+}
+
+pub fn slice_index_to_local(index: usize, y: i32) -> BlockCoords {
+    BlockCoords {
+        x: index as i32 % Slice::X_SIZE,
+        y,
+        z: index as i32 / Slice::X_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both sides of zero and of the first stack borders in each direction
+    const VALUES: [i32; 12] = [-33, -32, -17, -16, -15, -1, 0, 1, 15, 16, 17, 31];
+
+    #[test]
+    fn split_blocks_round_trip_in_every_quadrant() {
+        for x in VALUES {
+            for z in VALUES {
+                for y in [-64, -1, 0, 255] {
+                    let block = BlockCoords::new(x, y, z);
+                    let (stack, local) = split_block(block);
+
+                    assert_eq!(local_to_block(stack, local), block);
+                    assert!((0..Slice::X_SIZE).contains(&local.x) && (0..Slice::Z_SIZE).contains(&local.z), "{:?}", local);
+                    assert_eq!(local.y, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_block_in_a_range_round_trips() {
+        for x in -100..100 {
+            for z in -100..100 {
+                let block = BlockCoords::new(x, (x * 7 + z) % 64, z);
+                let (stack, local) = split_block(block);
+
+                assert_eq!(local_to_block(stack, local), block);
+                assert_eq!(stack, StackCoords { x: x.div_euclid(Slice::X_SIZE), z: z.div_euclid(Slice::Z_SIZE) });
+                assert_eq!(block_to_stack(block), stack);
+                assert_eq!(block_to_local(block), local);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_blocks_belong_to_negative_stacks() {
+        assert_eq!(split_block(BlockCoords::new(-1, 0, -1)), (StackCoords { x: -1, z: -1 }, BlockCoords::new(15, 0, 15)));
+        assert_eq!(split_block(BlockCoords::new(-16, 0, 16)), (StackCoords { x: -1, z: 1 }, BlockCoords::new(0, 0, 0)));
+        assert_eq!(split_block(BlockCoords::new(-17, 0, 15)), (StackCoords { x: -2, z: 0 }, BlockCoords::new(15, 0, 15)));
+    }
+
+    #[test]
+    fn world_positions_floor_towards_negative_infinity() {
+        assert_eq!(world_to_block(WorldCoords::new(-0.5, 0.5, -16.01)), BlockCoords::new(-1, 0, -17));
+        assert_eq!(world_to_stack(WorldCoords::new(-0.01, 0., 15.99)), StackCoords { x: -1, z: 0 });
+    }
+
+    #[test]
+    fn slice_indices_round_trip() {
+        for i in 0..Slice::SIZE {
+            assert_eq!(local_to_slice_index(slice_index_to_local(i, 7)), i);
+        }
+    }
+}
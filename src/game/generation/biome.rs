@@ -0,0 +1,129 @@
+use super::decoration::Feature;
+
+// Biomes are placed by climate. Every column gets a temperature, humidity and continentalness from
+// noise, and the biome whose climate lies closest is the one that column belongs to.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Biome {
+    Ocean,
+    Plains,
+    Desert,
+    Forest,
+    Mountains,
+}
+
+// What a biome looks like, block names are resolved through the registry when the generator is made
+pub struct BiomeSettings {
+    pub climate: [f64; 3], // Temperature, humidity, continentalness, all in [-1, 1]
+    pub base_height: f64,
+    pub height_variation: f64,
+    pub surface: &'static str,
+    pub filler: &'static str, // Between the surface and the stone
+    pub decorations: &'static [(Feature, f64)], // Average number per stack
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [Self::Ocean, Self::Plains, Self::Desert, Self::Forest, Self::Mountains];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Ocean => "ocean",
+            Biome::Plains => "plains",
+            Biome::Desert => "desert",
+            Biome::Forest => "forest",
+            Biome::Mountains => "mountains",
+        }
+    }
+
+    pub fn settings(&self) -> BiomeSettings {
+        match self {
+            Biome::Ocean => BiomeSettings {
+                climate: [0., 0., -1.],
+                base_height: 3.,
+                height_variation: 2.,
+                surface: "sand",
+                filler: "sand",
+                decorations: &[],
+            },
+            Biome::Plains => BiomeSettings {
+                climate: [0., 0., 0.4],
+                base_height: 11.,
+                height_variation: 2.,
+                surface: "grass",
+                filler: "dirt",
+                decorations: &[(Feature::Tree, 0.3), (Feature::Boulder, 0.2), (Feature::Hut, 0.03)],
+            },
+            Biome::Desert => BiomeSettings {
+                climate: [0.8, -0.6, 0.4],
+                base_height: 11.,
+                height_variation: 3.,
+                surface: "sand",
+                filler: "sand",
+                decorations: &[(Feature::Boulder, 0.1)],
+            },
+            Biome::Forest => BiomeSettings {
+                climate: [-0.1, 0.6, 0.4],
+                base_height: 12.,
+                height_variation: 4.,
+                surface: "grass",
+                filler: "dirt",
+                decorations: &[(Feature::Tree, 6.)],
+            },
+            Biome::Mountains => BiomeSettings {
+                climate: [-0.6, -0.2, 0.9],
+                base_height: 24.,
+                height_variation: 20.,
+                surface: "stone",
+                filler: "stone",
+                decorations: &[(Feature::Boulder, 1.), (Feature::Tree, 0.3)],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use super::super::terrain::NoiseTerrain;
+    use super::super::super::blocks::BlockRegistry;
+
+    fn terrain(seed: u64) -> NoiseTerrain {
+        NoiseTerrain::new(seed, &BlockRegistry::builtin()).unwrap()
+    }
+
+    // A coarse grid over a large area, wider than a biome
+    fn samples() -> impl Iterator<Item = (i32, i32)> {
+        (-20..20).flat_map(|x| (-20..20).map(move |z| (x * 97, z * 89)))
+    }
+
+    #[test]
+    fn the_same_seed_places_the_same_biomes() {
+        let (first, second, other) = (terrain(42), terrain(42), terrain(43));
+        assert!(samples().all(|(x, z)| first.biome_at(x, z) == second.biome_at(x, z)));
+        assert!(samples().any(|(x, z)| first.biome_at(x, z) != other.biome_at(x, z)));
+    }
+
+    #[test]
+    fn distant_columns_belong_to_different_biomes() {
+        let terrain = terrain(42);
+        let found = samples().map(|(x, z)| terrain.biome_at(x, z)).collect::<HashSet<Biome>>();
+        assert!(found.len() >= 3, "Only found {:?}", found);
+    }
+
+    #[test]
+    fn the_ground_blends_where_biomes_meet() {
+        let terrain = terrain(42);
+        let (mut borders, mut steepest) = (0, 0);
+        for x in -1000..1000 {
+            for z in (-1000..1000).step_by(50) {
+                if terrain.biome_at(x, z) != terrain.biome_at(x + 1, z) {
+                    borders += 1;
+                    steepest = steepest.max((terrain.height_at(x, z) - terrain.height_at(x + 1, z)).abs());
+                }
+            }
+        }
+        assert!(borders > 0);
+        assert!(steepest <= 2, "Biome borders step by up to {} blocks", steepest);
+    }
+}
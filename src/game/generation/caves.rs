@@ -0,0 +1,143 @@
+use super::noise::{ self, FractalNoise };
+use super::slice::Slice;
+use super::stack::Stack;
+use super::terrain::NoiseTerrain;
+use super::super::blocks::BlockRegistry;
+use super::super::coords;
+use super::super::units::{ BlockID, StackCoords };
+
+// Hollows tunnels and caverns out of generated terrain. Everything is sampled from 3D noise in world
+// coordinates, so a cave leaving one stack carries on in the next no matter which is generated first.
+//  Tunnels: where two independent noises are both close to zero, which traces long winding worms
+//  Caverns: where a third, lower frequency noise is high
+pub struct CaveCarver {
+    tunnels: [FractalNoise; 2],
+    caverns: FractalNoise,
+    water: BlockID,
+}
+
+impl CaveCarver {
+    const TUNNEL_OCTAVES: u32 = 2;
+    const TUNNEL_FREQUENCY: f64 = 1. / 48.;
+    const TUNNEL_WIDTH: f64 = 0.06;
+    const CAVERN_OCTAVES: u32 = 2;
+    const CAVERN_FREQUENCY: f64 = 1. / 32.;
+    const CAVERN_THRESHOLD: f64 = 0.36;
+    const VERTICAL_SQUASH: f64 = 2.; // Caves stretch further sideways than up and down
+
+    pub fn new(seed: u64, water: BlockID) -> Self {
+        let fractal = |salt: u64, octaves: u32, frequency: f64| FractalNoise::new(noise::sub_seed(seed, salt), octaves, frequency);
+        Self {
+            tunnels: [
+                fractal(10, Self::TUNNEL_OCTAVES, Self::TUNNEL_FREQUENCY),
+                fractal(11, Self::TUNNEL_OCTAVES, Self::TUNNEL_FREQUENCY),
+            ],
+            caverns: fractal(12, Self::CAVERN_OCTAVES, Self::CAVERN_FREQUENCY),
+            water,
+        }
+    }
+
+    // Whether the block at this world position belongs to a cave
+    pub fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let (x, y, z) = (x as f64, y as f64 * Self::VERTICAL_SQUASH, z as f64);
+
+        let (a, b) = (self.tunnels[0].get3(x, y, z), self.tunnels[1].get3(x, y, z));
+        if a * a + b * b < Self::TUNNEL_WIDTH * Self::TUNNEL_WIDTH {
+            return true;
+        }
+        self.caverns.get3(x, y, z) > Self::CAVERN_THRESHOLD
+    }
+
+    // Caves may open up at the surface on land, but never below water or through the bedrock floor
+    pub fn carve(&self, coords: StackCoords, stack: &mut Stack) {
+        let heights = stack.slices()
+            .map(|(y, _)| y)
+            .filter(|y| *y > NoiseTerrain::BEDROCK_LEVEL)
+            .collect::<Vec<_>>();
+
+        for y in heights {
+            for i in 0..Slice::SIZE {
+                let local = coords::slice_index_to_local(i, y);
+                let block = stack.get_block(local);
+                if block == BlockRegistry::AIR || block == self.water {
+                    continue;
+                }
+                // Keeps a seal under lakes and the sea, there is no flowing water to fill the cave
+                if stack.get_block(local + cgmath::Vector3::unit_y()) == self.water {
+                    continue;
+                }
+
+                let position = coords::local_to_block(coords, local);
+                if self.is_cave(position.x, position.y, position.z) {
+                    stack.set_block(local, BlockRegistry::AIR);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::units::BlockCoords;
+
+    const TOP: i32 = 64;
+
+    fn block(name: &str) -> BlockID {
+        BlockRegistry::builtin().id_of(name).unwrap()
+    }
+
+    // Stone from below the bedrock level up to the top, so every cell can be carved
+    fn carved(carver: &CaveCarver, coords: StackCoords) -> Stack {
+        let (mut stack, stone) = (Stack::new(), block("stone"));
+        for y in NoiseTerrain::BEDROCK_LEVEL - 8..TOP {
+            stack.insert_slice(y, Slice::new(stone));
+        }
+        carver.carve(coords, &mut stack);
+        stack
+    }
+
+    #[test]
+    fn caves_carry_on_across_stack_borders() {
+        // Separate carvers, as if the stacks were generated by different workers
+        let water = block("water");
+        let (first, second) = (CaveCarver::new(7, water), CaveCarver::new(7, water));
+        let (west, east) = (StackCoords { x: 0, z: 0 }, StackCoords { x: 1, z: 0 });
+        let (west_stack, east_stack) = (carved(&first, west), carved(&second, east));
+
+        let mut crossings = 0;
+        for y in NoiseTerrain::BEDROCK_LEVEL + 1..TOP {
+            for z in 0..Slice::Z_SIZE {
+                let west_air = west_stack.get_block(BlockCoords::new(Slice::X_SIZE - 1, y, z)) == BlockRegistry::AIR;
+                let east_air = east_stack.get_block(BlockCoords::new(0, y, z)) == BlockRegistry::AIR;
+                assert_eq!(west_air, second.is_cave(Slice::X_SIZE - 1, y, z));
+                assert_eq!(east_air, first.is_cave(Slice::X_SIZE, y, z));
+                if west_air && east_air {
+                    crossings += 1;
+                }
+            }
+        }
+        assert!(crossings > 0, "No cave crosses the border, pick another seed");
+    }
+
+    #[test]
+    fn the_bedrock_floor_is_never_carved() {
+        let (carver, stone) = (CaveCarver::new(3, block("water")), block("stone"));
+        let mut carved_cells = 0;
+        for x in -2..2 {
+            for z in -2..2 {
+                let stack = carved(&carver, StackCoords { x, z });
+                for (y, slice) in stack.slices() {
+                    if y <= NoiseTerrain::BEDROCK_LEVEL {
+                        assert_eq!(slice.uniform(), Some(stone), "Carved at y={}", y);
+                    } else {
+                        carved_cells += (0..Slice::SIZE)
+                            .filter(|i| stack.get_block(coords::slice_index_to_local(*i, y)) == BlockRegistry::AIR)
+                            .count();
+                    }
+                }
+            }
+        }
+        assert!(carved_cells > 0);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::{ HashMap, HashSet };
+
+use legion::{ IntoQuery, World };
+
+use super::worldblocks::WorldBlocks;
+use super::super::components::chunk_loader::{ ChunkLoader, SimulatedArea };
+use super::super::components::spatial::Position;
+use super::super::coords;
+use super::super::units::StackCoords;
+
+// Decides which stacks are in memory. Every entity with a ChunkLoader has the stacks around it loaded,
+// nearest first, and stacks that no loader wants anymore are saved and dropped. Stacks are only dropped
+// once they are a margin beyond every load radius, so walking back and forth over a stack border does
+// not keep loading and saving the same stacks.
+pub struct ChunkManager {
+    unload_margin: i32,
+}
+
+impl ChunkManager {
+    pub const DEFAULT_UNLOAD_MARGIN: i32 = 2;
+
+    pub fn new(unload_margin: i32) -> Self {
+        Self { unload_margin }
+    }
+
+    // Returns the stacks entities may be simulated in, all of them are loaded by then
+    pub fn update(&self, world: &World, blocks: &mut WorldBlocks) -> SimulatedArea {
+        blocks.receive_generated();
+
+        let loaders = <(&Position, &ChunkLoader)>::query().iter(world)
+            .map(|(position, loader)| (coords::world_to_stack(position.vector), *loader))
+            .collect::<Vec<_>>();
+
+        // The closest loader decides the priority
+        let mut wanted: HashMap<StackCoords, i32> = HashMap::new();
+        let mut simulated = HashSet::new();
+        for (center, loader) in &loaders {
+            for x in (center.x - loader.load_radius)..=(center.x + loader.load_radius) {
+                for z in (center.z - loader.load_radius)..=(center.z + loader.load_radius) {
+                    let coords = StackCoords { x, z };
+                    let distance = Self::distance(*center, coords);
+                    if distance > loader.load_radius.pow(2) {
+                        continue;
+                    }
+                    wanted.entry(coords).and_modify(|d| *d = (*d).min(distance)).or_insert(distance);
+                    if distance <= loader.sim_radius.pow(2) {
+                        simulated.insert(coords);
+                    }
+                }
+            }
+        }
+
+        // The simulation cannot wait for the workers, the rest of the stacks can
+        for coords in &simulated {
+            blocks.fault_in(*coords);
+        }
+        for (coords, priority) in &wanted {
+            blocks.request_stack(*coords, *priority);
+        }
+        blocks.cancel_requests(|coords| wanted.contains_key(&coords));
+
+        blocks.unload_where(|coords| loaders.iter().any(|(center, loader)| {
+            Self::distance(*center, coords) <= (loader.load_radius + self.unload_margin).pow(2)
+        }));
+
+        SimulatedArea { stacks: simulated }
+    }
+
+    // Squared, in stacks
+    fn distance(a: StackCoords, b: StackCoords) -> i32 {
+        (a.x - b.x).pow(2) + (a.z - b.z).pow(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cgmath::Point3;
+
+    use crate::util::TestDir;
+    use super::*;
+    use super::super::region::RegionStore;
+    use super::super::stack::Stack;
+    use super::super::super::blocks::BlockRegistry;
+
+    #[test]
+    fn walking_over_a_border_does_not_reload_stacks() {
+        let dir = TestDir::new("chunk-margin");
+        let mut blocks = WorldBlocks::new(Arc::new(BlockRegistry::builtin()));
+        for x in -6..16 {
+            for z in -6..7 {
+                blocks.insert_stack(StackCoords { x, z }, Stack::test_layout(1));
+            }
+        }
+        // Everything is saved and only read back once a loader wants it
+        blocks.set_storage(RegionStore::open(&dir.path).unwrap());
+        blocks.unload_where(|_| false);
+        assert_eq!(blocks.stacks().count(), 0);
+
+        let manager = ChunkManager::new(2);
+        let mut world = World::default();
+        let player = world.push((Position { vector: Point3::new(15.5, 10., 8.) }, ChunkLoader { load_radius: 2, sim_radius: 0 }));
+        let edge = StackCoords { x: -2, z: 0 }; // Wanted from stack 0, inside the margin from stack 1
+        let loaded = |blocks: &WorldBlocks, coords: StackCoords| blocks.stacks().any(|(c, _)| *c == coords);
+
+        manager.update(&world, &mut blocks);
+        assert!(loaded(&blocks, edge));
+        for step in 0..10 {
+            let x = if step % 2 == 0 { 16.5 } else { 15.5 };
+            world.entry(player).unwrap().get_component_mut::<Position>().unwrap().vector.x = x;
+            manager.update(&world, &mut blocks);
+            assert!(loaded(&blocks, edge), "Evicted at step {}", step);
+            assert!(loaded(&blocks, StackCoords { x: 3, z: 0 }));
+        }
+
+        // Beyond the margin it goes
+        world.entry(player).unwrap().get_component_mut::<Position>().unwrap().vector.x = 16. * 5. + 0.5;
+        manager.update(&world, &mut blocks);
+        assert!(!loaded(&blocks, edge));
+        assert!(loaded(&blocks, StackCoords { x: 7, z: 0 }));
+    }
+}
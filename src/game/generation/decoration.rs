@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use cgmath::Vector3;
+
+use super::biome::Biome;
+use super::noise::{ self, Noise, SeededRng };
+use super::slice::Slice;
+use super::stack::Stack;
+use super::super::blocks::BlockRegistry;
+use super::super::coords;
+use super::super::units::{ BlockCoords, BlockID, StackCoords };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    Tree,
+    Boulder,
+    Hut,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Self::Tree, Self::Boulder, Self::Hut];
+}
+
+// Decides which feature keeps a block where features overlap. A feature block lands on air and on the
+// feature blocks ranked below it, never on terrain. Since the stronger block always wins, overlapping
+// features come out the same whichever stack was generated first.
+pub struct FeaturePrecedence {
+    ranks: HashMap<BlockID, usize>,
+}
+
+impl FeaturePrecedence {
+    const ORDER: [&'static str; 5] = ["leaves", "planks", "cobblestone", "log", "lamp"]; // Weakest first
+
+    pub fn new(registry: &BlockRegistry) -> Self {
+        let ranks = Self::ORDER.iter().enumerate()
+            .filter_map(|(rank, name)| registry.id_of(name).map(|id| (id, rank)))
+            .collect();
+        Self { ranks }
+    }
+
+    pub fn replaces(&self, block: BlockID, existing: BlockID) -> bool {
+        existing == BlockRegistry::AIR || matches!((self.ranks.get(&block), self.ranks.get(&existing)),
+                                                  (Some(new), Some(old)) if new > old)
+    }
+}
+
+// A block a decoration wants to place in another stack, local to that stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingWrite {
+    pub local: BlockCoords,
+    pub block: BlockID,
+}
+
+impl PendingWrite {
+    pub fn apply(&self, stack: &mut Stack, precedence: &FeaturePrecedence) -> bool {
+        if !precedence.replaces(self.block, stack.get_block(self.local)) {
+            return false;
+        }
+        stack.set_block(self.local, self.block);
+        true
+    }
+}
+
+// Blocks the features are built from
+struct FeatureBlocks {
+    grass: BlockID,
+    water: BlockID,
+    log: BlockID,
+    leaves: BlockID,
+    cobblestone: BlockID,
+    planks: BlockID,
+    lamp: BlockID,
+}
+
+// Places trees, boulders and huts on generated terrain. Features start in the stack being generated
+// but may reach over its border, the blocks that fall into other stacks are handed back to be queued.
+pub struct Decorator {
+    seed: u64,
+    blocks: FeatureBlocks,
+    precedence: FeaturePrecedence,
+}
+
+impl Decorator {
+    const SEED_SALT: u64 = 200;
+
+    pub fn new(seed: u64, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let block = |name: &str| registry.id_of(name).ok_or(anyhow!("Decorations need a {} block", name));
+        Ok(Self {
+            seed,
+            blocks: FeatureBlocks {
+                grass: block("grass")?,
+                water: block("water")?,
+                log: block("log")?,
+                leaves: block("leaves")?,
+                cobblestone: block("cobblestone")?,
+                planks: block("planks")?,
+                lamp: block("lamp")?,
+            },
+            precedence: FeaturePrecedence::new(registry),
+        })
+    }
+
+    // Features are tried at random columns as often as the densest biome wants them, then each try is kept
+    // with the chance the column's own biome wants, so every biome ends up with its own density
+    pub fn decorate(&self, coords: StackCoords, stack: &mut Stack, surface: impl Fn(i32, i32) -> (i32, Biome))
+        -> Vec<(StackCoords, PendingWrite)> {
+        let mut rng = SeededRng::new(Noise::new(noise::sub_seed(self.seed, Self::SEED_SALT)).hash(coords.x, 0, coords.z));
+        let mut overflow = Vec::new();
+
+        for feature in Feature::ALL {
+            let density = |biome: Biome| biome.settings().decorations.iter()
+                .find(|(f, _)| *f == feature)
+                .map(|(_, density)| *density)
+                .unwrap_or(0.);
+            let max_density = Biome::ALL.into_iter().map(density).fold(0., f64::max);
+            let tries = max_density.floor() as u32 + (rng.next_f64() < max_density.fract()) as u32;
+
+            for _ in 0..tries {
+                let local = BlockCoords::new(rng.range(0, Slice::X_SIZE - 1), 0, rng.range(0, Slice::Z_SIZE - 1));
+                let column = coords::local_to_block(coords, local);
+                let (height, biome) = surface(column.x, column.z);
+                let keep = rng.next_f64() * max_density < density(biome);
+                let ground = stack.get_block(BlockCoords { y: height, ..local });
+                if !keep || !self.can_stand_on(feature, ground) || stack.get_block(BlockCoords { y: height + 1, ..local }) != BlockRegistry::AIR {
+                    continue;
+                }
+
+                let mut blocks = Vec::new();
+                let base = BlockCoords { y: height + 1, ..column };
+                match feature {
+                    Feature::Tree => self.tree(base, &mut rng, &mut blocks),
+                    Feature::Boulder => self.boulder(base, &mut rng, &mut blocks),
+                    Feature::Hut => self.hut(base, &mut blocks),
+                }
+
+                for (position, block) in blocks {
+                    if !Stack::in_height(position.y) {
+                        continue;
+                    }
+                    let (owner, local) = coords::split_block(position);
+                    let write = PendingWrite { local, block };
+                    if owner == coords {
+                        write.apply(stack, &self.precedence);
+                    } else {
+                        overflow.push((owner, write));
+                    }
+                }
+            }
+        }
+
+        overflow
+    }
+
+    // Trees only grow on grass, everything else needs dry ground that was not carved away
+    fn can_stand_on(&self, feature: Feature, ground: BlockID) -> bool {
+        match feature {
+            Feature::Tree => ground == self.blocks.grass,
+            _ => ground != BlockRegistry::AIR && ground != self.blocks.water
+        }
+    }
+
+    // A trunk with two wide layers of leaves around its top and a narrow crown
+    fn tree(&self, base: BlockCoords, rng: &mut SeededRng, blocks: &mut Vec<(BlockCoords, BlockID)>) {
+        let height = rng.range(4, 6);
+        for y in 0..height {
+            blocks.push((base + Vector3::new(0, y, 0), self.blocks.log));
+        }
+
+        for y in (height - 2)..=(height + 1) {
+            let radius: i32 = if y < height { 2 } else { 1 };
+            for x in -radius..=radius {
+                for z in -radius..=radius {
+                    // Round off the corners, randomly on the wide layers
+                    if x.abs() == radius && z.abs() == radius && (radius == 1 || rng.next_u64() & 1 == 0) {
+                        continue;
+                    }
+                    blocks.push((base + Vector3::new(x, y, z), self.blocks.leaves));
+                }
+            }
+        }
+    }
+
+    // A lump of cobblestone, filling in any gaps in the ground around its base
+    fn boulder(&self, base: BlockCoords, rng: &mut SeededRng, blocks: &mut Vec<(BlockCoords, BlockID)>) {
+        let radius = rng.range(1, 2);
+        for x in -radius..=radius {
+            for y in -1..=radius {
+                for z in -radius..=radius {
+                    if x * x + y * y + z * z <= radius * radius + 1 {
+                        blocks.push((base + Vector3::new(x, y, z), self.blocks.cobblestone));
+                    }
+                }
+            }
+        }
+    }
+
+    // Four plank walls with a doorway, a flat roof and a lamp under it
+    fn hut(&self, base: BlockCoords, blocks: &mut Vec<(BlockCoords, BlockID)>) {
+        const SIZE: i32 = 5;
+        const WALL_HEIGHT: i32 = 3;
+        for x in 0..SIZE {
+            for z in 0..SIZE {
+                let position = base + Vector3::new(x, 0, z);
+                let wall = x == 0 || z == 0 || x == SIZE - 1 || z == SIZE - 1;
+                let door = z == 0 && x == SIZE / 2;
+                for y in 0..WALL_HEIGHT {
+                    if wall && !(door && y < 2) {
+                        blocks.push((position + Vector3::new(0, y, 0), self.blocks.planks));
+                    }
+                }
+                blocks.push((position + Vector3::new(0, WALL_HEIGHT, 0), self.blocks.planks));
+            }
+        }
+        blocks.push((base + Vector3::new(SIZE / 2, WALL_HEIGHT - 1, SIZE / 2), self.blocks.lamp));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use super::super::worldblocks::WorldBlocks;
+
+    // Every stack of the area, encoded, after generating them in the given order
+    fn generate_area(order: &[StackCoords]) -> Vec<Vec<u8>> {
+        let mut world = WorldBlocks::generated(Arc::new(BlockRegistry::builtin()), 7).unwrap();
+        for coords in order {
+            assert!(world.fault_in(*coords));
+        }
+
+        let mut sorted = order.to_vec();
+        sorted.sort_by_key(|coords| (coords.x, coords.z));
+        sorted.iter()
+            .map(|coords| world.stacks().find(|(c, _)| *c == coords).unwrap().1.encode().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn conflicting_writes_do_not_depend_on_arrival_order() {
+        let registry = Arc::new(BlockRegistry::builtin());
+        let id = |name: &str| registry.id_of(name).unwrap();
+        let target = StackCoords { x: -1, z: 2 };
+        let write = |x, y, z, name: &str| (target, PendingWrite { local: BlockCoords::new(x, y, z), block: id(name) });
+        // Writes from two neighbours that overlap, one of them also over terrain
+        let first = vec![write(0, 10, 0, "leaves"), write(1, 10, 0, "log"), write(2, 10, 0, "planks"), write(3, 5, 0, "log")];
+        let second = vec![write(0, 10, 0, "log"), write(1, 10, 0, "leaves"), write(2, 10, 0, "cobblestone"), write(4, 10, 0, "leaves")];
+
+        let results = [(&first, &second, true), (&second, &first, true), (&first, &second, false), (&second, &first, false)]
+            .map(|(a, b, loaded_first)| {
+                let mut world = WorldBlocks::new(registry.clone());
+                let mut stack = Some(Stack::new());
+                stack.as_mut().unwrap().set_block(BlockCoords::new(3, 5, 0), id("stone"));
+                if loaded_first {
+                    world.insert_stack(target, stack.take().unwrap());
+                }
+                world.queue_writes(a.iter().copied());
+                world.queue_writes(b.iter().copied());
+                if let Some(stack) = stack {
+                    world.insert_stack(target, stack);
+                }
+                (0..5).map(|x| world.get_block(coords::local_to_block(target, BlockCoords::new(x, if x == 3 { 5 } else { 10 }, 0))).unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+        assert_eq!(results[0], vec![id("log"), id("log"), id("cobblestone"), id("stone"), id("leaves")]);
+        assert!(results.iter().all(|result| *result == results[0]));
+    }
+
+    #[test]
+    fn overlapping_features_do_not_depend_on_generation_order() {
+        let area = (-2..2).flat_map(|x| (-2..2).map(move |z| StackCoords { x, z })).collect::<Vec<_>>();
+        let reversed = area.iter().rev().copied().collect::<Vec<_>>();
+        // Every other stack first, so most stacks get their neighbours' blocks after they were generated
+        let interleaved = area.iter().step_by(2).chain(area.iter().skip(1).step_by(2)).copied().collect::<Vec<_>>();
+
+        let expected = generate_area(&area);
+        assert_eq!(generate_area(&reversed), expected);
+        assert_eq!(generate_area(&interleaved), expected);
+    }
+}
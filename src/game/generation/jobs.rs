@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+use std::collections::{ BinaryHeap, HashMap, HashSet };
+use std::sync::{ Arc, Condvar, Mutex, mpsc };
+use std::thread::{ self, JoinHandle };
+
+use super::super::units::StackCoords;
+
+// Work on stacks done by a pool of background threads. Jobs are picked closest first, finished results
+// are collected through a channel on the thread that owns the world.
+//  Requesting a stack that is already queued only updates its priority
+//  Requesting a stack that a worker is busy with keeps the result coming
+//  Cancelled stacks are skipped if still queued and their result dropped if already being worked on
+pub struct JobPool<T> {
+    shared: Arc<Shared>,
+    results: mpsc::Receiver<(StackCoords, T)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+#[derive(Default)]
+struct Queue {
+    heap: BinaryHeap<Job>,
+    queued: HashMap<StackCoords, (u64, i32)>, // Ticket and priority of the current heap entry, older entries are stale
+    running: HashSet<StackCoords>, // Taken by a worker, until the result is collected
+    cancelled: HashSet<StackCoords>, // Running jobs whose result is no longer wanted
+    next_ticket: u64,
+    shutdown: bool,
+}
+
+struct Job {
+    priority: i32, // Lower runs first
+    ticket: u64,
+    coords: StackCoords,
+}
+
+// Reversed so the max heap pops the lowest priority, ties go to the oldest request
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.cmp(&self.priority).then(other.ticket.cmp(&self.ticket))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl<T: Send + 'static> JobPool<T> {
+    const MAX_WORKERS: usize = 4;
+
+    // One thread less than the machine has, leaving a core for the game and renderer
+    pub fn new(name: &str, work: impl Fn(StackCoords) -> T + Send + Sync + 'static) -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1))
+            .unwrap_or(1)
+            .clamp(1, Self::MAX_WORKERS);
+        Self::with_threads(name, threads, work)
+    }
+
+    pub fn with_threads(name: &str, threads: usize, work: impl Fn(StackCoords) -> T + Send + Sync + 'static) -> Self {
+        let shared = Arc::new(Shared { queue: Mutex::new(Queue::default()), available: Condvar::new() });
+        let work = Arc::new(work);
+        let (sender, results) = mpsc::channel();
+
+        let workers = (0..threads)
+            .filter_map(|i| {
+                let (shared, work, sender) = (shared.clone(), work.clone(), sender.clone());
+                thread::Builder::new()
+                    .name(format!("{} {}", name, i))
+                    .spawn(move || Self::run(&shared, &*work, &sender))
+                    .map_err(|e| log::error!("Unable to start {} worker: {}", name, e))
+                    .ok()
+            })
+            .collect();
+
+        Self { shared, results, workers }
+    }
+
+    // Queues the stack or moves it to the new priority if it is queued already
+    pub fn request(&self, coords: StackCoords, priority: i32) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.running.contains(&coords) {
+            queue.cancelled.remove(&coords);
+            return;
+        }
+
+        if queue.queued.get(&coords).is_some_and(|(_, queued)| *queued == priority) {
+            return;
+        }
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        queue.queued.insert(coords, (ticket, priority));
+        queue.heap.push(Job { priority, ticket, coords });
+        drop(queue);
+        self.shared.available.notify_one();
+    }
+
+    // Whether a result for the stack is still on its way
+    pub fn is_pending(&self, coords: StackCoords) -> bool {
+        let queue = self.shared.queue.lock().unwrap();
+        queue.queued.contains_key(&coords) || (queue.running.contains(&coords) && !queue.cancelled.contains(&coords))
+    }
+
+    pub fn cancel(&self, coords: StackCoords) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.queued.remove(&coords);
+        if queue.running.contains(&coords) {
+            queue.cancelled.insert(coords);
+        }
+    }
+
+    // Cancels every stack the predicate rejects
+    pub fn retain(&self, mut keep: impl FnMut(StackCoords) -> bool) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.queued.retain(|coords, _| keep(*coords));
+        let unwanted = queue.running.iter().copied().filter(|coords| !keep(*coords)).collect::<Vec<_>>();
+        queue.cancelled.extend(unwanted);
+
+        // Drop stale entries once they outnumber the live ones so the heap does not grow without bound
+        if queue.heap.len() > 2 * queue.queued.len() + 64 {
+            let Queue { heap, queued, .. } = &mut *queue;
+            heap.retain(|job| queued.get(&job.coords).is_some_and(|(ticket, _)| *ticket == job.ticket));
+        }
+    }
+
+    // Results finished since the last call, without waiting for more
+    pub fn finished(&self) -> Vec<(StackCoords, T)> {
+        let results = self.results.try_iter().collect::<Vec<_>>();
+        if results.is_empty() {
+            return results;
+        }
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        results.into_iter()
+            .filter(|(coords, _)| {
+                queue.running.remove(coords);
+                !queue.cancelled.remove(coords)
+            })
+            .collect()
+    }
+
+    fn run(shared: &Shared, work: &impl Fn(StackCoords) -> T, sender: &mpsc::Sender<(StackCoords, T)>) {
+        loop {
+            let coords = {
+                let mut queue = shared.queue.lock().unwrap();
+                loop {
+                    if queue.shutdown {
+                        return;
+                    }
+                    match queue.heap.pop() {
+                        Some(job) if queue.queued.get(&job.coords).is_some_and(|(ticket, _)| *ticket == job.ticket) => {
+                            queue.queued.remove(&job.coords);
+                            queue.running.insert(job.coords);
+                            break job.coords;
+                        },
+                        Some(_) => continue,
+                        None => queue = shared.available.wait(queue).unwrap()
+                    }
+                }
+            };
+
+            let result = work(coords);
+
+            let mut queue = shared.queue.lock().unwrap();
+            if queue.cancelled.remove(&coords) {
+                queue.running.remove(&coords);
+            } else if sender.send((coords, result)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl<T> Drop for JobPool<T> {
+    // Queued jobs are abandoned, running ones are finished before their thread exits
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, Instant };
+
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    // A single worker that reports every job it starts and only finishes one for every message on the gate
+    fn gated_pool() -> (JobPool<StackCoords>, mpsc::Receiver<StackCoords>, mpsc::Sender<()>) {
+        let (started, starts) = mpsc::channel();
+        let (gate, gate_receiver) = mpsc::channel::<()>();
+        let (started, gate_receiver) = (Mutex::new(started), Mutex::new(gate_receiver));
+        let pool = JobPool::with_threads("Test", 1, move |coords| {
+            started.lock().unwrap().send(coords).unwrap();
+            let _ = gate_receiver.lock().unwrap().recv();
+            coords
+        });
+        (pool, starts, gate)
+    }
+
+    fn stack(x: i32) -> StackCoords {
+        StackCoords { x, z: 0 }
+    }
+
+    // Collects results until there are as many as expected
+    fn results(pool: &JobPool<StackCoords>, count: usize) -> Vec<StackCoords> {
+        let deadline = Instant::now() + WAIT;
+        let mut results = Vec::new();
+        while results.len() < count && Instant::now() < deadline {
+            results.extend(pool.finished().into_iter().map(|(coords, result)| {
+                assert_eq!(coords, result);
+                coords
+            }));
+            thread::sleep(Duration::from_millis(1));
+        }
+        results
+    }
+
+    #[test]
+    fn nearer_jobs_run_first() {
+        let (pool, starts, gate) = gated_pool();
+        pool.request(stack(0), 0);
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(0))); // Keeps the worker busy while the others queue
+
+        pool.request(stack(1), 10);
+        pool.request(stack(2), 1);
+        pool.request(stack(3), 5);
+        for _ in 0..4 {
+            gate.send(()).unwrap();
+        }
+
+        let order = (0..3).map(|_| starts.recv_timeout(WAIT).unwrap()).collect::<Vec<_>>();
+        assert_eq!(order, [stack(2), stack(3), stack(1)]);
+        assert_eq!(results(&pool, 4), [stack(0), stack(2), stack(3), stack(1)]);
+    }
+
+    #[test]
+    fn requesting_twice_gives_one_result() {
+        let (pool, starts, gate) = gated_pool();
+        pool.request(stack(0), 0);
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(0)));
+
+        pool.request(stack(0), 0); // Running already
+        pool.request(stack(1), 3);
+        pool.request(stack(1), 3);
+        pool.request(stack(1), 1); // Queued, only moves
+        for _ in 0..2 {
+            gate.send(()).unwrap();
+        }
+
+        let mut done = results(&pool, 2);
+        thread::sleep(Duration::from_millis(50));
+        done.extend(pool.finished().into_iter().map(|(coords, _)| coords));
+        assert_eq!(done, [stack(0), stack(1)]);
+        assert!(starts.try_iter().eq([stack(1)]));
+        assert!(!pool.is_pending(stack(0)) && !pool.is_pending(stack(1)));
+    }
+
+    #[test]
+    fn cancelled_queued_jobs_never_run() {
+        let (pool, starts, gate) = gated_pool();
+        pool.request(stack(0), 0);
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(0)));
+
+        pool.request(stack(1), 1);
+        pool.request(stack(2), 2);
+        assert!(pool.is_pending(stack(1)));
+        pool.cancel(stack(1));
+        assert!(!pool.is_pending(stack(1)));
+
+        gate.send(()).unwrap();
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(2)));
+        gate.send(()).unwrap();
+        assert_eq!(results(&pool, 2), [stack(0), stack(2)]);
+        assert!(starts.try_recv().is_err());
+    }
+
+    #[test]
+    fn cancelled_running_jobs_are_discarded() {
+        let (pool, starts, gate) = gated_pool();
+        pool.request(stack(0), 0);
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(0)));
+        pool.cancel(stack(0));
+        assert!(!pool.is_pending(stack(0)));
+
+        // The next job only starts once the cancelled one is done
+        pool.request(stack(1), 0);
+        gate.send(()).unwrap();
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(1)));
+        gate.send(()).unwrap();
+        assert_eq!(results(&pool, 1), [stack(1)]);
+        assert!(pool.finished().is_empty());
+    }
+
+    #[test]
+    fn requesting_a_cancelled_running_job_keeps_its_result() {
+        let (pool, starts, gate) = gated_pool();
+        pool.request(stack(0), 0);
+        assert_eq!(starts.recv_timeout(WAIT), Ok(stack(0)));
+        pool.cancel(stack(0));
+        pool.request(stack(0), 0);
+        assert!(pool.is_pending(stack(0)));
+
+        gate.send(()).unwrap();
+        assert_eq!(results(&pool, 1), [stack(0)]);
+        assert!(starts.try_recv().is_err());
+    }
+}
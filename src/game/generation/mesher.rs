@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use cgmath::{ EuclideanSpace, Vector3 };
+
+use crate::graphics::cube_render::stack_mesh::StackMesh;
+use super::slice::Slice;
+use super::stack::Stack;
+use super::super::blocks::{ BlockFace, BlockRegistry };
+use super::super::coords;
+use super::super::units::{ BlockCoords, BlockID, StackCoords };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    Culled, // A quad for every visible face
+    Greedy, // Neighbouring faces with the same texture are merged into larger quads
+}
+
+impl MeshingMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Culled => Self::Greedy,
+            Self::Greedy => Self::Culled
+        }
+    }
+}
+
+// Stacks bordering the one being meshed, missing stacks are treated as air
+#[derive(Clone, Copy, Default)]
+pub struct StackNeighbours<'a> {
+    pub neg_x: Option<&'a Stack>,
+    pub pos_x: Option<&'a Stack>,
+    pub neg_z: Option<&'a Stack>,
+    pub pos_z: Option<&'a Stack>,
+}
+
+// Geometry of a face on the unit block at the origin, the top left corner of its texture and
+// the directions its texture runs in. down x right always points out of the block.
+struct FaceGeometry {
+    top_left: Vector3<f32>,
+    right: Vector3<f32>,
+    down: Vector3<f32>,
+}
+
+impl BlockFace {
+    pub fn normal(&self) -> Vector3<i32> {
+        match self {
+            BlockFace::Front => Vector3::new(0, 0, -1),
+            BlockFace::Right => Vector3::new(1, 0, 0),
+            BlockFace::Back => Vector3::new(0, 0, 1),
+            BlockFace::Left => Vector3::new(-1, 0, 0),
+            BlockFace::Top => Vector3::new(0, 1, 0),
+            BlockFace::Bottom => Vector3::new(0, -1, 0),
+        }
+    }
+
+    fn geometry(&self) -> FaceGeometry {
+        let (top_left, right, down) = match self {
+            BlockFace::Front => ([1., 1., 0.], [-1., 0., 0.], [0., -1., 0.]),
+            BlockFace::Right => ([1., 1., 1.], [0., 0., -1.], [0., -1., 0.]),
+            BlockFace::Back => ([0., 1., 1.], [1., 0., 0.], [0., -1., 0.]),
+            BlockFace::Left => ([0., 1., 0.], [0., 0., 1.], [0., -1., 0.]),
+            BlockFace::Top => ([0., 1., 0.], [1., 0., 0.], [0., 0., 1.]),
+            BlockFace::Bottom => ([0., 0., 1.], [1., 0., 0.], [0., 0., -1.]),
+        };
+        FaceGeometry { top_left: top_left.into(), right: right.into(), down: down.into() }
+    }
+}
+
+// Which of x, y and z an axis aligned unit vector runs along, and whether it is positive
+fn axis_of(vector: Vector3<f32>) -> (usize, bool) {
+    let axis = (0..3).find(|i| vector[*i] != 0.).expect("Face directions are axis aligned");
+    (axis, vector[axis] > 0.)
+}
+
+pub struct Mesher<'a> {
+    stack: &'a Stack,
+    neighbours: StackNeighbours<'a>,
+    registry: &'a BlockRegistry,
+    mode: MeshingMode,
+}
+
+impl<'a> Mesher<'a> {
+    pub fn new(stack: &'a Stack, neighbours: StackNeighbours<'a>, registry: &'a BlockRegistry, mode: MeshingMode) -> Self {
+        Self { stack, neighbours, registry, mode }
+    }
+
+    pub fn mesh(&self, coords: StackCoords) -> StackMesh {
+        let origin = coords::stack_origin(coords).cast::<f32>().unwrap().to_vec();
+        match self.mode {
+            MeshingMode::Culled => self.mesh_culled(origin),
+            MeshingMode::Greedy => self.mesh_greedy(origin),
+        }
+    }
+
+    // One quad for every face of a visible block that is not hidden behind an opaque neighbour
+    fn mesh_culled(&self, origin: Vector3<f32>) -> StackMesh {
+        let mut mesh = StackMesh::new();
+
+        self.visible_faces(|local, face, index| {
+            let geometry = face.geometry();
+            let corner = local.cast::<f32>().unwrap() + origin + geometry.top_left;
+            mesh.push_quad([
+                corner.into(),
+                (corner + geometry.down).into(),
+                (corner + geometry.down + geometry.right).into(),
+                (corner + geometry.right).into(),
+            ], [1., 1.], index);
+        });
+
+        mesh
+    }
+
+    // Visible faces are sorted into planes, one for each face direction and layer along its normal.
+    // Each plane is then covered with the largest rectangles of matching faces, growing along the
+    // texture's right direction first and then down. The texture repeats once per block across a quad.
+    fn mesh_greedy(&self, origin: Vector3<f32>) -> StackMesh {
+        let mut planes: HashMap<(BlockFace, i32), HashMap<(i32, i32), u32>> = HashMap::new();
+        self.visible_faces(|local, face, index| {
+            let geometry = face.geometry();
+            let (normal_axis, _) = axis_of(face.normal().cast().unwrap());
+            let ((right_axis, _), (down_axis, _)) = (axis_of(geometry.right), axis_of(geometry.down));
+            planes.entry((face, local[normal_axis]))
+                .or_default()
+                .insert((local[right_axis], local[down_axis]), index);
+        });
+
+        let mut mesh = StackMesh::new();
+        for ((face, layer), mut plane) in planes {
+            let geometry = face.geometry();
+            let (normal_axis, _) = axis_of(face.normal().cast().unwrap());
+            let ((right_axis, right_positive), (down_axis, down_positive)) = (axis_of(geometry.right), axis_of(geometry.down));
+
+            let mut cells = plane.keys().copied().collect::<Vec<_>>();
+            cells.sort_by_key(|(a, b)| (*b, *a));
+
+            for (a, b) in cells {
+                let index = match plane.get(&(a, b)) {
+                    Some(index) => *index,
+                    None => continue  // Already part of another quad
+                };
+
+                let mut width = 1;
+                while plane.get(&(a + width, b)) == Some(&index) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while (0..width).all(|i| plane.get(&(a + i, b + height)) == Some(&index)) {
+                    height += 1;
+                }
+                for i in 0..width {
+                    for j in 0..height {
+                        plane.remove(&(a + i, b + j));
+                    }
+                }
+
+                // The quad starts at the block holding the top left corner of the texture
+                let mut block = Vector3::new(0, 0, 0);
+                block[normal_axis] = layer;
+                block[right_axis] = if right_positive { a } else { a + width - 1 };
+                block[down_axis] = if down_positive { b } else { b + height - 1 };
+
+                let (right, down) = (geometry.right * width as f32, geometry.down * height as f32);
+                let corner = block.cast::<f32>().unwrap() + origin + geometry.top_left;
+                mesh.push_quad([
+                    corner.into(),
+                    (corner + down).into(),
+                    (corner + down + right).into(),
+                    (corner + right).into(),
+                ], [width as f32, height as f32], index);
+            }
+        }
+
+        mesh
+    }
+
+    // Calls back with the local position, face and face table index of every face that can be seen
+    fn visible_faces(&self, mut callback: impl FnMut(BlockCoords, BlockFace, u32)) {
+        for (y, slice) in self.stack.slices() {
+            for i in 0..Slice::SIZE {
+                let local = coords::slice_index_to_local(i, y);
+                let block = slice.get(local);
+                if !self.registry.is_visible(block) {
+                    continue;
+                }
+
+                for face in BlockFace::ALL {
+                    if self.face_visible(block, local + face.normal()) {
+                        callback(local, face, Self::face_index(block, face));
+                    }
+                }
+            }
+        }
+    }
+
+    // A face can be seen through transparent neighbours, unless it is the same block like water next to water
+    fn face_visible(&self, block: BlockID, neighbour: BlockCoords) -> bool {
+        let neighbour = self.block_at(neighbour);
+        neighbour != block && self.registry.is_transparent(neighbour)
+    }
+
+    // Local coordinates that may be up to one block outside of the stack
+    fn block_at(&self, local: BlockCoords) -> BlockID {
+        let stack = if local.x < 0 {
+            self.neighbours.neg_x
+        } else if local.x >= Slice::X_SIZE {
+            self.neighbours.pos_x
+        } else if local.z < 0 {
+            self.neighbours.neg_z
+        } else if local.z >= Slice::Z_SIZE {
+            self.neighbours.pos_z
+        } else {
+            Some(self.stack)
+        };
+
+        match stack {
+            Some(stack) => stack.get_block(coords::block_to_local(local)),
+            None => BlockRegistry::AIR
+        }
+    }
+
+    pub fn face_index(block: BlockID, face: BlockFace) -> u32 {
+        block as u32 * BlockFace::ALL.len() as u32 + face as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use super::super::worldblocks::WorldBlocks;
+    use super::super::super::units::EntityCoords;
+
+    fn quads(mesh: &StackMesh) -> usize {
+        mesh.indices.len() / 6
+    }
+
+    // Faces covered, a quad covers its texture size in faces
+    fn area(mesh: &StackMesh) -> f32 {
+        mesh.vertices.chunks(4).map(|quad| quad[2].tex_pos[0] * quad[2].tex_pos[1]).sum()
+    }
+
+    #[test]
+    fn greedy_meshing_covers_the_same_faces_with_fewer_quads() {
+        let registry = BlockRegistry::builtin();
+        let (grass, stone) = (registry.id_of("grass").unwrap(), registry.id_of("stone").unwrap());
+        let mut stack = Stack::test_layout(grass);
+        for i in 0..16 {
+            stack.set_block(BlockCoords::new(i, 3, (i * 7) % 16), stone);
+            stack.set_block(BlockCoords::new(i / 2, 4 + i % 3, 5), stone);
+        }
+        stack.set_block(BlockCoords::new(8, 1, 8), BlockRegistry::AIR); // Hidden cave, its faces are still meshed
+
+        let coords = StackCoords { x: -2, z: 3 };
+        let culled = Mesher::new(&stack, StackNeighbours::default(), &registry, MeshingMode::Culled).mesh(coords);
+        let greedy = Mesher::new(&stack, StackNeighbours::default(), &registry, MeshingMode::Greedy).mesh(coords);
+
+        assert!(quads(&greedy) < quads(&culled));
+        assert_eq!(area(&culled), quads(&culled) as f32);
+        assert_eq!(area(&greedy), area(&culled));
+    }
+
+    #[test]
+    fn solid_stack_only_has_outer_faces() {
+        let registry = BlockRegistry::builtin();
+        let stack = Stack::test_layout(registry.id_of("stone").unwrap());
+        let coords = StackCoords { x: 0, z: 0 };
+
+        // Top and bottom plus four sides, 16 wide and 3 high
+        let outer = 2 * 16 * 16 + 4 * 16 * 3;
+        let culled = Mesher::new(&stack, StackNeighbours::default(), &registry, MeshingMode::Culled).mesh(coords);
+        assert_eq!(quads(&culled), outer);
+
+        let greedy = Mesher::new(&stack, StackNeighbours::default(), &registry, MeshingMode::Greedy).mesh(coords);
+        assert_eq!(quads(&greedy), 6);
+        assert_eq!(area(&greedy), quads(&culled) as f32);
+
+        // Solid neighbours hide the sides
+        let neighbours = StackNeighbours { neg_x: Some(&stack), pos_x: Some(&stack), neg_z: Some(&stack), pos_z: Some(&stack) };
+        let enclosed = Mesher::new(&stack, neighbours, &registry, MeshingMode::Culled).mesh(coords);
+        assert_eq!(quads(&enclosed), 2 * 16 * 16);
+    }
+
+    #[test]
+    fn toggling_the_mode_remeshes_every_stack() {
+        let mut world = WorldBlocks::test_layout(Arc::new(BlockRegistry::builtin()));
+        let center = EntityCoords::new(24., 10., 24.);
+        let total_quads = |meshes: &[(StackCoords, StackMesh)]| meshes.iter().map(|(_, mesh)| quads(mesh)).sum::<usize>();
+
+        let (visible, greedy) = world.get_stack_meshes(center);
+        assert_eq!((visible.len(), greedy.len()), (9, 9));
+        assert!(world.get_stack_meshes(center).1.is_empty()); // Nothing changed
+
+        world.set_meshing_mode(world.meshing_mode().toggled());
+        assert_eq!(world.meshing_mode(), MeshingMode::Culled);
+        let culled = world.get_stack_meshes(center).1;
+        assert_eq!(culled.len(), 9);
+        assert!(total_quads(&culled) > total_quads(&greedy));
+
+        world.set_meshing_mode(world.meshing_mode().toggled());
+        let again = world.get_stack_meshes(center).1;
+        assert_eq!(again.len(), 9);
+        assert_eq!(total_quads(&again), total_quads(&greedy));
+    }
+}
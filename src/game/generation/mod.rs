@@ -1,4 +1,17 @@
 pub mod active_block;
 pub mod worldblocks;
 pub mod stack;
-pub mod slice;
\ No newline at end of file
+pub mod slice;
+pub mod section;
+pub mod save;
+pub mod region;
+pub mod mesher;
+pub mod noise;
+pub mod terrain;
+pub mod biome;
+pub mod caves;
+pub mod ores;
+pub mod decoration;
+pub mod jobs;
+pub mod chunk_manager;
+pub mod raycast;
\ No newline at end of file
@@ -0,0 +1,174 @@
+// Seeded gradient noise. Only integer hashing and plain float arithmetic is used so the same seed
+// gives the same values on every run and platform, worlds regenerate identically from their seed.
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    seed: u64,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    // Well mixed 64 bits for a lattice point, also useful for anything else that needs positional randomness
+    pub fn hash(&self, x: i32, y: i32, z: i32) -> u64 {
+        let mut h = self.seed
+            ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        // splitmix64 finalizer
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^ (h >> 31)
+    }
+
+    // Roughly in [-1, 1], 0 on every integer lattice point
+    pub fn get2(&self, x: f64, z: f64) -> f64 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let (ix, iz) = (x0 as i32, z0 as i32);
+
+        let corner = |cx: i32, cz: i32| {
+            let (dx, dz) = (fx - cx as f64, fz - cz as f64);
+            match self.hash(ix + cx, 0, iz + cz) & 7 {
+                0 => dx + dz,
+                1 => dx - dz,
+                2 => -dx + dz,
+                3 => -dx - dz,
+                4 => dx,
+                5 => -dx,
+                6 => dz,
+                _ => -dz,
+            }
+        };
+
+        let (u, v) = (fade(fx), fade(fz));
+        lerp(v,
+            lerp(u, corner(0, 0), corner(1, 0)),
+            lerp(u, corner(0, 1), corner(1, 1)))
+    }
+
+    // Roughly in [-1, 1], 0 on every integer lattice point
+    pub fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+
+        // The 12 edge directions of a cube, like improved Perlin noise
+        let corner = |cx: i32, cy: i32, cz: i32| {
+            let (dx, dy, dz) = (fx - cx as f64, fy - cy as f64, fz - cz as f64);
+            match self.hash(ix + cx, iy + cy, iz + cz) % 12 {
+                0 => dx + dy,
+                1 => -dx + dy,
+                2 => dx - dy,
+                3 => -dx - dy,
+                4 => dx + dz,
+                5 => -dx + dz,
+                6 => dx - dz,
+                7 => -dx - dz,
+                8 => dy + dz,
+                9 => -dy + dz,
+                10 => dy - dz,
+                _ => -dy - dz,
+            }
+        };
+
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        lerp(w,
+            lerp(v,
+                lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+                lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+            lerp(v,
+                lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+                lerp(u, corner(0, 1, 1), corner(1, 1, 1))))
+    }
+}
+
+// Several octaves of noise added together, each at a higher frequency and lower amplitude than the last
+#[derive(Clone, Copy, Debug)]
+pub struct FractalNoise {
+    noise: Noise,
+    octaves: u32,
+    frequency: f64, // Of the first octave, in cycles per block
+}
+
+impl FractalNoise {
+    const LACUNARITY: f64 = 2.;
+    const PERSISTENCE: f64 = 0.5;
+
+    pub fn new(seed: u64, octaves: u32, frequency: f64) -> Self {
+        Self { noise: Noise::new(seed), octaves, frequency }
+    }
+
+    // Normalised back into roughly [-1, 1]
+    pub fn get2(&self, x: f64, z: f64) -> f64 {
+        self.sum(|octave, frequency| {
+            // Offsetting every octave keeps their zeroes on the lattice points from lining up
+            let offset = octave as f64 * 17.31;
+            self.noise.get2(x * frequency + offset, z * frequency - offset)
+        })
+    }
+
+    pub fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.sum(|octave, frequency| {
+            let offset = octave as f64 * 17.31;
+            self.noise.get3(x * frequency + offset, y * frequency + offset, z * frequency - offset)
+        })
+    }
+
+    fn sum(&self, sample: impl Fn(u32, f64) -> f64) -> f64 {
+        let (mut total, mut amplitude, mut frequency, mut max) = (0., 1., self.frequency, 0.);
+        for octave in 0..self.octaves {
+            total += sample(octave, frequency) * amplitude;
+            max += amplitude;
+            amplitude *= Self::PERSISTENCE;
+            frequency *= Self::LACUNARITY;
+        }
+        total / max
+    }
+}
+
+// Small deterministic generator for placing things, seeded from a position hash so every stack
+// draws the same numbers however often it is generated
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // splitmix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [low, high]
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        debug_assert!(low <= high);
+        low + (self.next_u64() % (high - low + 1) as u64) as i32
+    }
+}
+
+// Seed for one of several independent noises made from the same world seed
+pub fn sub_seed(seed: u64, salt: u64) -> u64 {
+    Noise::new(seed).hash(salt as i32, (salt >> 32) as i32, 0x5EED)
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
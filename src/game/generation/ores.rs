@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ anyhow, bail, Context };
+use cgmath::Vector3;
+
+use super::noise::{ self, Noise, SeededRng };
+use super::slice::Slice;
+use super::stack::Stack;
+use super::super::blocks::BlockRegistry;
+use super::super::coords;
+use super::super::units::{ BlockCoords, BlockID, StackCoords };
+
+#[derive(Clone, Debug)]
+pub struct OreDefinition {
+    pub name: String,
+    pub block: BlockID,
+    pub replaces: BlockID,
+    pub heights: (i32, i32), // Inclusive
+    pub vein_size: u32,
+    pub frequency: f64, // Average veins per stack
+}
+
+// Every ore the generator scatters, from resources/ores.txt so new ores only need a data change
+#[derive(Clone, Debug, Default)]
+pub struct OreTable {
+    pub ores: Vec<OreDefinition>,
+}
+
+impl OreTable {
+    pub const DEFAULT_PATH: &'static str = "resources/ores.txt";
+
+    pub fn load(path: &Path, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read ore definitions {}", path.display()))?;
+        Self::parse(&text, registry).with_context(|| format!("Invalid ore definitions {}", path.display()))
+    }
+
+    // The data file if it can be used, otherwise the table compiled into the executable, otherwise no ores
+    pub fn load_default(registry: &BlockRegistry) -> Self {
+        let error = match Self::load(Path::new(Self::DEFAULT_PATH), registry) {
+            Ok(table) => return table,
+            Err(e) => e
+        };
+        log::warn!("Using builtin ore definitions: {:#}", error);
+
+        Self::parse(include_str!("../../../resources/ores.txt"), registry).unwrap_or_else(|e| {
+            log::error!("Builtin ore definitions do not fit the blocks, generating without ores: {:#}", e);
+            Self::default()
+        })
+    }
+
+    pub fn parse(text: &str, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let stone = registry.id_of("stone");
+        let mut ores: Vec<(usize, OreDefinition, bool)> = Vec::new(); // Line, ore, whether a block was given
+
+        for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.trim();
+                if ores.iter().any(|(_, ore, _)| ore.name == name) {
+                    bail!("Line {}: ore {} is defined twice", number, name);
+                }
+                let ore = OreDefinition {
+                    name: name.to_owned(),
+                    block: BlockRegistry::AIR,
+                    replaces: stone.ok_or(anyhow!("Line {}: ores replace stone unless told otherwise, but there is no stone block", number))?,
+                    heights: (Stack::MIN_HEIGHT, Stack::MAX_HEIGHT - 1),
+                    vein_size: 8,
+                    frequency: 1.,
+                };
+                ores.push((number, ore, false));
+                continue;
+            }
+
+            let (ore, has_block) = match ores.last_mut() {
+                Some((_, ore, has_block)) => (ore, has_block),
+                None => bail!("Line {}: property outside of an ore", number)
+            };
+            let (key, value) = line.split_once('=').ok_or(anyhow!("Line {}: expected key = value", number))?;
+            let (key, value) = (key.trim(), value.trim());
+            let block = |name: &str| registry.id_of(name).ok_or(anyhow!("Line {}: unknown block {}", number, name));
+
+            match key {
+                "block" => {
+                    ore.block = block(value)?;
+                    *has_block = true;
+                },
+                "replaces" => ore.replaces = block(value)?,
+                "heights" => ore.heights = match value.split_whitespace().map(|h| h.parse()).collect::<Result<Vec<i32>, _>>() {
+                    Ok(heights) if heights.len() == 2 && heights[0] <= heights[1] => (heights[0], heights[1]),
+                    _ => bail!("Line {}: expected the lowest and highest height", number)
+                },
+                "vein_size" => ore.vein_size = value.parse().with_context(|| format!("Line {}: invalid vein size", number))?,
+                "frequency" => ore.frequency = value.parse().with_context(|| format!("Line {}: invalid frequency", number))?,
+                _ => bail!("Line {}: unknown property {}", number, key)
+            }
+        }
+
+        for (number, ore, has_block) in &ores {
+            if !has_block {
+                bail!("Ore {} on line {} has no block", ore.name, number);
+            }
+            if ore.frequency.is_nan() || ore.frequency < 0. {
+                bail!("Ore {} needs a frequency of at least 0", ore.name);
+            }
+            if ore.vein_size as i32 > Slice::X_SIZE.min(Slice::Z_SIZE) * OreScatter::VEIN_REACH {
+                bail!("Veins of ore {} are larger than the {} stacks they may spread over", ore.name, OreScatter::VEIN_REACH);
+            }
+        }
+
+        Ok(Self { ores: ores.into_iter().map(|(_, ore, _)| ore).collect() })
+    }
+}
+
+// Places ore veins into generated stacks. Veins start at a random position in a stack and wander from
+// there, so the veins of neighbouring stacks are traced again and whatever part of them falls into this
+// stack is placed too. That keeps veins whole across stack borders whatever order stacks generate in.
+pub struct OreScatter {
+    seed: u64,
+    table: OreTable,
+}
+
+impl OreScatter {
+    const VEIN_REACH: i32 = 1; // In stacks
+    const SEED_SALT: u64 = 100;
+
+    pub fn new(seed: u64, table: OreTable) -> Self {
+        Self { seed, table }
+    }
+
+    pub fn place(&self, coords: StackCoords, stack: &mut Stack) {
+        for (index, ore) in self.table.ores.iter().enumerate() {
+            let noise = Noise::new(noise::sub_seed(self.seed, Self::SEED_SALT + index as u64));
+
+            for dx in -Self::VEIN_REACH..=Self::VEIN_REACH {
+                for dz in -Self::VEIN_REACH..=Self::VEIN_REACH {
+                    let origin = StackCoords { x: coords.x + dx, z: coords.z + dz };
+                    let mut rng = SeededRng::new(noise.hash(origin.x, 0, origin.z));
+
+                    Self::veins(ore, origin, &mut rng, |position| {
+                        let (owner, local) = coords::split_block(position);
+                        if owner == coords && stack.get_block(local) == ore.replaces {
+                            stack.set_block(local, ore.block);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    // Calls back with every position of every vein that starts in the origin stack
+    fn veins(ore: &OreDefinition, origin: StackCoords, rng: &mut SeededRng, mut place: impl FnMut(BlockCoords)) {
+        let count = ore.frequency.floor() as u32 + (rng.next_f64() < ore.frequency.fract()) as u32;
+        let (low, high) = ore.heights;
+
+        for _ in 0..count {
+            let mut position = coords::local_to_block(origin, BlockCoords::new(
+                rng.range(0, Slice::X_SIZE - 1),
+                rng.range(low, high),
+                rng.range(0, Slice::Z_SIZE - 1),
+            ));
+
+            for _ in 0..ore.vein_size {
+                if (low..=high).contains(&position.y) {
+                    place(position);
+                }
+
+                let mut step = Vector3::new(0, 0, 0);
+                step[rng.range(0, 2) as usize] = if rng.next_u64() & 1 == 0 { 1 } else { -1 };
+                position += step;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACKS: i32 = 20; // Along each side of the generated area
+
+    // Blocks of every ore in a square of stone stacks, and the lowest and highest y each was found at
+    fn scatter(table: OreTable, registry: &BlockRegistry) -> Vec<(usize, i32, i32)> {
+        let stone = registry.id_of("stone").unwrap();
+        let ores = table.ores.clone();
+        let scatter = OreScatter::new(11, table);
+        let mut found = vec![(0, i32::MAX, i32::MIN); ores.len()];
+
+        for x in 0..STACKS {
+            for z in 0..STACKS {
+                let mut stack = Stack::new();
+                for y in -40..30 {
+                    stack.insert_slice(y, Slice::new(stone));
+                }
+                scatter.place(StackCoords { x, z }, &mut stack);
+
+                for (y, slice) in stack.slices() {
+                    for i in 0..Slice::SIZE {
+                        let block = slice.get(coords::slice_index_to_local(i, y));
+                        if let Some(index) = ores.iter().position(|o| o.block == block) {
+                            let (count, low, high) = &mut found[index];
+                            *count += 1;
+                            *low = (*low).min(y);
+                            *high = (*high).max(y);
+                        }
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    #[test]
+    fn single_block_veins_match_their_frequency() {
+        let registry = BlockRegistry::builtin();
+        let table = OreTable::parse("[a]\nblock = coal_ore\nheights = -31 20\nvein_size = 1\nfrequency = 1.5\n\
+                                     [b]\nblock = iron_ore\nheights = -10 -10\nvein_size = 1\nfrequency = 0.25\n", &registry).unwrap();
+
+        let stacks = (STACKS * STACKS) as f64;
+        let found = scatter(table.clone(), &registry);
+        for (ore, (count, low, high)) in table.ores.iter().zip(found) {
+            // Within three standard deviations, counting veins as if they were poisson distributed
+            let per_stack = count as f64 / stacks;
+            let tolerance = 3. * (ore.frequency / stacks).sqrt();
+            assert!((per_stack - ore.frequency).abs() < tolerance, "{} has {} per stack", ore.name, per_stack);
+            assert!(low >= ore.heights.0 && high <= ore.heights.1, "{} between {} and {}", ore.name, low, high);
+        }
+    }
+
+    #[test]
+    fn builtin_ores_stay_within_their_definitions() {
+        let registry = BlockRegistry::builtin();
+        let table = OreTable::parse(include_str!("../../../resources/ores.txt"), &registry).unwrap();
+
+        let stacks = (STACKS * STACKS) as f64;
+        let found = scatter(table.clone(), &registry);
+        for (ore, (count, low, high)) in table.ores.iter().zip(found) {
+            // Veins cross themselves and get cut off at their heights, but most of their steps place a block
+            let per_stack = count as f64 / stacks;
+            let most = ore.frequency * ore.vein_size as f64;
+            assert!(per_stack <= most && per_stack >= most * 0.4, "{} has {} per stack, at most {}", ore.name, per_stack, most);
+            assert!(low >= ore.heights.0 && high <= ore.heights.1, "{} between {} and {}", ore.name, low, high);
+
+            // Enough blocks that the whole range is used
+            assert!(low - ore.heights.0 <= 2 && ore.heights.1 - high <= 2, "{} between {} and {}", ore.name, low, high);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use cgmath::{ InnerSpace, Vector3, Zero };
+
+use super::worldblocks::WorldBlocks;
+use super::super::components::spatial::{ Direction, Position };
+use super::super::coords;
+use super::super::units::{ BlockCoords, BlockID };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub block: BlockCoords,
+    pub id: BlockID,
+    pub normal: Vector3<i32>, // Of the face the ray entered through, zero if it started inside the block
+    pub distance: f32, // Along the ray to where it entered the block
+    pub adjacent: BlockCoords, // The empty cell in front of the face, the block itself if the ray started inside it
+}
+
+impl WorldBlocks {
+    // Walks the ray block by block (Amanatides and Woo) and returns the first solid block, so it passes
+    // through air and water.
+    // Stops without a hit at the reach, at stacks that can not be loaded, or for a zero direction.
+    pub fn raycast(&mut self, origin: &Position, direction: &Direction, reach: f32) -> Option<RaycastHit> {
+        if direction.vector.is_zero() {
+            return None;
+        }
+        let (origin, direction) = (origin.vector, direction.vector.normalize());
+
+        let mut block = coords::world_to_block(origin);
+        let mut step = Vector3::zero();
+        let mut t_max = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY); // Distance to the next border on each axis
+        let mut t_delta = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY); // Distance between borders on each axis
+        for axis in 0..3 {
+            if direction[axis] > 0. {
+                step[axis] = 1;
+                t_max[axis] = (block[axis] as f32 + 1. - origin[axis]) / direction[axis];
+            } else if direction[axis] < 0. {
+                step[axis] = -1;
+                t_max[axis] = (origin[axis] - block[axis] as f32) / -direction[axis];
+            }
+            if direction[axis] != 0. {
+                t_delta[axis] = 1. / direction[axis].abs();
+            }
+        }
+
+        let (mut normal, mut distance) = (Vector3::zero(), 0.);
+        loop {
+            let id = self.get_block(block)?;
+            if self.registry().is_solid(id) {
+                return Some(RaycastHit { block, id, normal, distance, adjacent: block + normal });
+            }
+
+            let axis = if t_max.x < t_max.y {
+                if t_max.x < t_max.z { 0 } else { 2 }
+            } else if t_max.y < t_max.z { 1 } else { 2 };
+            if t_max[axis] > reach {
+                return None;
+            }
+
+            distance = t_max[axis];
+            block[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = Vector3::zero();
+            normal[axis] = -step[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cgmath::Point3;
+
+    use super::*;
+    use super::super::stack::Stack;
+    use super::super::super::blocks::BlockRegistry;
+    use super::super::super::units::StackCoords;
+
+    const STONE: BlockID = 3;
+
+    // Empty stacks around the origin, radius in stacks
+    fn world(radius: i32) -> WorldBlocks {
+        let mut world = WorldBlocks::new(Arc::new(BlockRegistry::builtin()));
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                world.insert_stack(StackCoords { x, z }, Stack::new());
+            }
+        }
+        world
+    }
+
+    fn ray(world: &mut WorldBlocks, origin: (f32, f32, f32), direction: (f32, f32, f32), reach: f32) -> Option<RaycastHit> {
+        world.raycast(&Position { vector: Point3::new(origin.0, origin.1, origin.2) },
+                      &Direction { vector: Vector3::new(direction.0, direction.1, direction.2) }, reach)
+    }
+
+    #[test]
+    fn hits_along_every_axis() {
+        let mut world = world(1);
+        let origin = BlockCoords::new(0, 10, 0);
+        for axis in 0..3 {
+            for sign in [-1, 1] {
+                let mut offset = Vector3::zero();
+                offset[axis] = 3 * sign;
+                world.set_block(origin + offset, STONE);
+
+                let mut direction = Vector3::zero();
+                direction[axis] = sign as f32 * 2.; // Does not have to be normalized
+                let hit = ray(&mut world, (0.5, 10.5, 0.5), direction.into(), 10.).unwrap();
+                assert_eq!(hit.block, origin + offset);
+                assert_eq!(hit.id, STONE);
+                assert_eq!(hit.normal, -offset / 3);
+                assert!((hit.distance - 2.5).abs() < 1e-5);
+                assert_eq!(hit.adjacent, origin + offset * 2 / 3);
+
+                world.set_block(origin + offset, BlockRegistry::AIR);
+            }
+        }
+    }
+
+    #[test]
+    fn crosses_stack_borders_at_negative_coordinates() {
+        let mut world = world(2);
+        let block = BlockCoords::new(-18, 20, -17); // In stack (-2, -2), the ray starts in (-1, -1)
+        world.set_block(block, STONE);
+
+        let hit = ray(&mut world, (-15.5, 20.5, -15.5), (-2., 0., -1.), 10.).unwrap();
+        assert_eq!(hit.block, block);
+        assert_eq!(hit.normal, Vector3::new(1, 0, 0));
+        assert!((hit.distance - 0.75 * 5f32.sqrt()).abs() < 1e-5);
+        assert_eq!(hit.adjacent, BlockCoords::new(-17, 20, -17));
+    }
+
+    #[test]
+    fn stops_at_the_reach() {
+        let mut world = world(1);
+        world.set_block(BlockCoords::new(3, 10, 0), STONE);
+
+        assert!(ray(&mut world, (0.5, 10.5, 0.5), (1., 0., 0.), 2.4).is_none());
+        assert!(ray(&mut world, (0.5, 10.5, 0.5), (1., 0., 0.), 2.6).is_some());
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it() {
+        let mut world = world(1);
+        world.set_block(BlockCoords::new(-1, -5, -1), STONE);
+
+        let hit = ray(&mut world, (-0.5, -4.5, -0.5), (0., 1., 0.), 5.).unwrap();
+        assert_eq!(hit.block, BlockCoords::new(-1, -5, -1));
+        assert_eq!(hit.normal, Vector3::zero());
+        assert_eq!(hit.distance, 0.);
+        assert_eq!(hit.adjacent, hit.block);
+    }
+
+    #[test]
+    fn stops_at_unloaded_stacks() {
+        let mut world = world(0);
+        world.set_block(BlockCoords::new(15, 10, 0), STONE);
+
+        // Leaving the only loaded stack ends the ray, even with reach left
+        assert!(ray(&mut world, (8.5, 10.5, 0.5), (-1., 0., 0.), 20.).is_none());
+        assert!(ray(&mut world, (8.5, 10.5, 0.5), (0., 0., 1.), 20.).is_none());
+        assert!(ray(&mut world, (8.5, 10.5, 0.5), (1., 0., 0.), 20.).is_some());
+    }
+
+    #[test]
+    fn passes_through_water() {
+        let mut world = world(1);
+        let water = world.registry().id_of("water").unwrap();
+        world.set_block(BlockCoords::new(0, 5, 0), STONE);
+        for y in 6..10 {
+            world.set_block(BlockCoords::new(0, y, 0), water);
+        }
+
+        let hit = ray(&mut world, (0.5, 10.5, 0.5), (0., -1., 0.), 10.).unwrap();
+        assert_eq!((hit.block, hit.id), (BlockCoords::new(0, 5, 0), STONE));
+        assert_eq!(hit.adjacent, BlockCoords::new(0, 6, 0));
+        assert!(ray(&mut world, (0.5, 10.5, 0.5), (0., -1., 0.), 4.).is_none());
+    }
+
+    #[test]
+    fn zero_direction_hits_nothing() {
+        let mut world = world(0);
+        world.set_block(BlockCoords::new(0, 10, 0), STONE);
+        assert!(ray(&mut world, (0.5, 10.5, 0.5), (0., 0., 0.), 5.).is_none());
+    }
+}
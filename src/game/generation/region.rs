@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ Read, Seek, SeekFrom, Write };
+use std::path::{ Path, PathBuf };
+
+use anyhow::{ bail, Context };
+
+use super::stack::Stack;
+use super::super::units::StackCoords;
+
+// A region file holds a REGION_SIZE x REGION_SIZE square of stacks, split into fixed size sectors.
+// Sector 0 is the offset table: one u32 per stack, with the first sector of the stack in the upper
+// 24 bits and the number of sectors it occupies in the lower 8 bits. Zero means the stack was never written.
+// Every stack starts with its u32 byte length, followed by its encoded data.
+pub const REGION_SIZE: i32 = 32;
+pub const SECTOR_SIZE: usize = 4096;
+const REGION_STACKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const HEADER_SECTORS: u32 = 1;
+const MAX_STACK_SECTORS: usize = u8::MAX as usize;
+const REGION_EXTENSION: &str = "mtr";
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct RegionCoords {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionCoords {
+    pub fn of(coords: &StackCoords) -> Self {
+        Self {
+            x: coords.x.div_euclid(REGION_SIZE),
+            z: coords.z.div_euclid(REGION_SIZE),
+        }
+    }
+
+    // Position of a stack in the offset table
+    fn table_index(coords: &StackCoords) -> usize {
+        (coords.x.rem_euclid(REGION_SIZE) + coords.z.rem_euclid(REGION_SIZE) * REGION_SIZE) as usize
+    }
+}
+
+pub struct RegionFile {
+    file: File,
+    offsets: Box<[u32; REGION_STACKS]>,
+    used_sectors: Vec<bool>,
+}
+
+impl RegionFile {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+            .with_context(|| format!("Unable to open region file {}", path.display()))?;
+
+        let mut offsets = Box::new([0u32; REGION_STACKS]);
+        let length = file.metadata()?.len() as usize;
+        if length == 0 {
+            file.write_all(&[0u8; SECTOR_SIZE * HEADER_SECTORS as usize])?;
+        } else {
+            if length < SECTOR_SIZE {
+                bail!("Region file {} is truncated", path.display());
+            }
+            let mut header = [0u8; SECTOR_SIZE];
+            file.read_exact(&mut header)?;
+            for (offset, bytes) in offsets.iter_mut().zip(header.chunks_exact(4)) {
+                *offset = u32::from_le_bytes(bytes.try_into().unwrap());
+            }
+        }
+
+        // Mark every sector that a stack lives in, anything left over can be reused
+        let mut used_sectors = vec![false; length.div_ceil(SECTOR_SIZE).max(HEADER_SECTORS as usize)];
+        for sector in used_sectors.iter_mut().take(HEADER_SECTORS as usize) {
+            *sector = true;
+        }
+        for offset in offsets.iter().filter(|offset| **offset != 0) {
+            let (start, count) = Self::split_offset(*offset);
+            if start + count > used_sectors.len() {
+                used_sectors.resize(start + count, false);
+            }
+            used_sectors[start..start + count].fill(true);
+        }
+
+        Ok(Self {
+            file,
+            offsets,
+            used_sectors,
+        })
+    }
+
+    pub fn read_stack(&mut self, coords: &StackCoords) -> anyhow::Result<Option<Stack>> {
+        let offset = self.offsets[RegionCoords::table_index(coords)];
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        let (start, count) = Self::split_offset(offset);
+        self.file.seek(SeekFrom::Start((start * SECTOR_SIZE) as u64))?;
+
+        // A damaged length must not make us allocate or read past the stack's own sectors
+        let mut length = [0u8; 4];
+        self.file.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length + 4 > count * SECTOR_SIZE {
+            bail!("Stack {:?} claims {} bytes but only has {} sectors", coords, length, count);
+        }
+        let mut data = vec![0u8; length];
+        self.file.read_exact(&mut data)?;
+
+        Ok(Some(Stack::decode(&data)?))
+    }
+
+    pub fn write_stack(&mut self, coords: &StackCoords, stack: &Stack) -> anyhow::Result<()> {
+        let data = stack.encode()?;
+        let needed = (data.len() + 4).div_ceil(SECTOR_SIZE);
+        if needed > MAX_STACK_SECTORS {
+            bail!("Stack {:?} is too large for a region file", coords);
+        }
+
+        let index = RegionCoords::table_index(coords);
+        let (old_start, old_count) = Self::split_offset(self.offsets[index]);
+
+        // Reuse the old sectors if the stack still fits, otherwise release them and find a new run
+        let start = if self.offsets[index] != 0 && needed <= old_count {
+            self.used_sectors[old_start + needed..old_start + old_count].fill(false);
+            old_start
+        } else {
+            if self.offsets[index] != 0 {
+                self.used_sectors[old_start..old_start + old_count].fill(false);
+            }
+            self.allocate(needed)
+        };
+        self.used_sectors[start..start + needed].fill(true);
+
+        let mut sectors = vec![0u8; needed * SECTOR_SIZE];
+        sectors[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        sectors[4..4 + data.len()].copy_from_slice(&data);
+        self.file.seek(SeekFrom::Start((start * SECTOR_SIZE) as u64))?;
+        self.file.write_all(&sectors)?;
+
+        self.offsets[index] = ((start as u32) << 8) | needed as u32;
+        self.file.seek(SeekFrom::Start((index * 4) as u64))?;
+        self.file.write_all(&self.offsets[index].to_le_bytes())?;
+
+        Ok(())
+    }
+
+    // First run of free sectors long enough to hold count sectors, growing the file if there is none
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run = 0;
+        for (i, used) in self.used_sectors.iter().enumerate() {
+            run = if *used { 0 } else { run + 1 };
+            if run == count {
+                return i + 1 - count;
+            }
+        }
+
+        let start = self.used_sectors.len() - run;
+        self.used_sectors.resize(start + count, false);
+        start
+    }
+
+    fn split_offset(offset: u32) -> (usize, usize) {
+        ((offset >> 8) as usize, (offset & 0xFF) as usize)
+    }
+}
+
+// Lazily opens the region files of a world directory as stacks are requested
+pub struct RegionStore {
+    dir: PathBuf,
+    regions: HashMap<RegionCoords, RegionFile>,
+}
+
+impl RegionStore {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create region directory {}", dir.display()))?;
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            regions: HashMap::new(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn load_stack(&mut self, coords: &StackCoords) -> anyhow::Result<Option<Stack>> {
+        match self.region(coords, false)? {
+            Some(region) => region.read_stack(coords),
+            None => Ok(None)
+        }
+    }
+
+    pub fn save_stack(&mut self, coords: &StackCoords, stack: &Stack) -> anyhow::Result<()> {
+        match self.region(coords, true)? {
+            Some(region) => region.write_stack(coords, stack),
+            None => unreachable!("Region files are always created when saving")
+        }
+    }
+
+    // Region holding the stack, only touching the disk the first time a region is needed
+    fn region(&mut self, coords: &StackCoords, create: bool) -> anyhow::Result<Option<&mut RegionFile>> {
+        let region_coords = RegionCoords::of(coords);
+        if !self.regions.contains_key(&region_coords) {
+            let path = self.region_path(&region_coords);
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            self.regions.insert(region_coords, RegionFile::open(&path)?);
+        }
+
+        Ok(self.regions.get_mut(&region_coords))
+    }
+
+    fn region_path(&self, coords: &RegionCoords) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}", coords.x, coords.z, REGION_EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::TestDir;
+    use super::*;
+    use super::super::super::units::BlockCoords;
+
+    // A stack that differs for every coordinate, noisy enough to need several sectors if tall
+    fn stack(coords: &StackCoords, layers: i32) -> Stack {
+        let mut state = (coords.x as u64) << 32 ^ coords.z as u32 as u64 ^ 0x9E37_79B9_7F4A_7C15;
+        let mut stack = Stack::new();
+        for y in 0..layers {
+            for x in 0..16 {
+                for z in 0..16 {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    stack.set_block(BlockCoords::new(x, y, z), (state >> 59) as u8);
+                }
+            }
+        }
+        stack
+    }
+
+    #[test]
+    fn round_trips_thousands_of_stacks() {
+        let dir = TestDir::new("region-round-trip");
+        let all = (-25..25).flat_map(|x| (-20..20).map(move |z| StackCoords { x, z })).collect::<Vec<_>>();
+
+        let mut store = RegionStore::open(&dir.path).unwrap();
+        for coords in &all {
+            store.save_stack(coords, &stack(coords, 1)).unwrap();
+        }
+        drop(store);
+
+        // A fresh store has to read everything back from disk
+        let mut store = RegionStore::open(&dir.path).unwrap();
+        for coords in &all {
+            let loaded = store.load_stack(coords).unwrap().unwrap();
+            assert_eq!(loaded.encode().unwrap(), stack(coords, 1).encode().unwrap(), "stack {:?}", coords);
+        }
+        assert!(store.load_stack(&StackCoords { x: 25, z: 0 }).unwrap().is_none());
+        assert!(store.load_stack(&StackCoords { x: 1000, z: -1000 }).unwrap().is_none());
+    }
+
+    #[test]
+    fn reuses_sectors_freed_by_a_grown_stack() {
+        let dir = TestDir::new("region-reuse");
+        let path = dir.path.join("r.0.0.mtr");
+        let (a, b, c) = (StackCoords { x: 0, z: 0 }, StackCoords { x: 1, z: 0 }, StackCoords { x: 2, z: 0 });
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_stack(&a, &stack(&a, 1)).unwrap();
+        region.write_stack(&b, &stack(&b, 1)).unwrap();
+        let (old_start, old_count) = RegionFile::split_offset(region.offsets[RegionCoords::table_index(&a)]);
+
+        // Growing past its sectors moves the stack behind the others
+        let grown = stack(&a, 64);
+        region.write_stack(&a, &grown).unwrap();
+        let (start, count) = RegionFile::split_offset(region.offsets[RegionCoords::table_index(&a)]);
+        assert!(count > old_count);
+        assert_ne!(start, old_start);
+
+        // The next small stack goes where the grown one used to be
+        region.write_stack(&c, &stack(&c, 1)).unwrap();
+        assert_eq!(RegionFile::split_offset(region.offsets[RegionCoords::table_index(&c)]).0, old_start);
+        let length = region.file.metadata().unwrap().len() as usize;
+        drop(region);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_stack(&a).unwrap().unwrap().encode().unwrap(), grown.encode().unwrap());
+        assert_eq!(region.read_stack(&b).unwrap().unwrap().encode().unwrap(), stack(&b, 1).encode().unwrap());
+        assert_eq!(region.read_stack(&c).unwrap().unwrap().encode().unwrap(), stack(&c, 1).encode().unwrap());
+        assert_eq!(region.file.metadata().unwrap().len() as usize, length);
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_stack_sectors() {
+        let dir = TestDir::new("region-length");
+        let path = dir.path.join("r.0.0.mtr");
+        let coords = StackCoords { x: 3, z: 4 };
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_stack(&coords, &stack(&coords, 1)).unwrap();
+        let (start, _) = RegionFile::split_offset(region.offsets[RegionCoords::table_index(&coords)]);
+        region.file.seek(SeekFrom::Start((start * SECTOR_SIZE) as u64)).unwrap();
+        region.file.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        drop(region);
+
+        assert!(RegionFile::open(&path).unwrap().read_stack(&coords).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use std::fs;
+use std::io::{ Read, Write };
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{ anyhow, bail, Context };
+use flate2::{ Compression, read::ZlibDecoder, write::ZlibEncoder };
+
+use super::decoration::PendingWrite;
+use super::region::RegionStore;
+use super::super::blocks::BlockRegistry;
+use super::section::Section;
+use super::stack::Stack;
+use super::slice::Slice;
+use super::worldblocks::WorldBlocks;
+use super::super::units::{ BlockCoords, StackCoords };
+
+// On-disk layout of a world directory:
+//   <dir>/world.dat             header, magic + version + terrain seed, version 2 had no seed
+//   <dir>/regions/r.<x>.<z>.mtr  region files, see region.rs
+//   <dir>/pending.dat           decoration blocks for stacks that were never generated
+//
+// Version 1 worlds kept one encoded stack per file in <dir>/stacks/<x>_<z>.stk and had a stack
+// count in place of the seed. Loading one moves its stacks into region files.
+//
+// An encoded stack is the stack magic and version, followed by a zlib stream holding the section
+// count and then every section that holds blocks as its index, a 16 bit mask of the layers it has
+// slices for, lowest layer in the lowest bit, and those slices. A slice is its index width, its
+// palette length minus one, its palette and its packed indices.
+// Version 2 stored a slice count and every slice as its height plus the slice, version 1 stored
+// every slice as its height plus its raw block ids.
+//
+// The pending file is its magic and version, followed by a zlib stream holding the stack count
+// and then every stack as its coordinates, its write count and the writes as x, y, z and block.
+pub const WORLD_MAGIC: &[u8; 4] = b"MTWD";
+pub const STACK_MAGIC: &[u8; 4] = b"MTST";
+pub const PENDING_MAGIC: &[u8; 4] = b"MTPW";
+pub const WORLD_FORMAT_VERSION: u16 = 3;
+pub const STACK_FORMAT_VERSION: u16 = 3;
+pub const PENDING_FORMAT_VERSION: u16 = 1;
+// Terrain seed of worlds saved before the header held one, they are rewritten with it on save
+pub const LEGACY_SEED: u64 = 0;
+
+const WORLD_FILE: &str = "world.dat";
+const REGION_DIR: &str = "regions";
+const PENDING_FILE: &str = "pending.dat";
+const STACK_DIR: &str = "stacks";
+const STACK_EXTENSION: &str = "stk";
+
+impl Stack {
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        output.extend_from_slice(STACK_MAGIC);
+        output.extend_from_slice(&STACK_FORMAT_VERSION.to_le_bytes());
+
+        // Sorted so the same stack always encodes to the same bytes
+        let mut sections = self.sections().filter(|(_, section)| !section.is_empty()).collect::<Vec<_>>();
+        sections.sort_by_key(|(y, _)| *y);
+
+        let mut encoder = ZlibEncoder::new(output, Compression::default());
+        encoder.write_all(&(sections.len() as u32).to_le_bytes())?;
+        for (y, section) in sections {
+            let mask = section.slices().fold(0u16, |mask, (layer, _)| mask | 1 << layer);
+            encoder.write_all(&y.to_le_bytes())?;
+            encoder.write_all(&mask.to_le_bytes())?;
+            for (_, slice) in section.slices() {
+                write_slice(&mut encoder, slice)?;
+            }
+        }
+
+        Ok(encoder.finish()?)
+    }
+
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < STACK_MAGIC.len() + 2 || &data[..STACK_MAGIC.len()] != STACK_MAGIC {
+            bail!("Not an encoded stack");
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version == 0 || version > STACK_FORMAT_VERSION {
+            bail!("Unsupported stack format version {}", version);
+        }
+
+        let mut decoder = ZlibDecoder::new(&data[6..]);
+        let count = read_u32(&mut decoder)?;
+
+        let mut stack = Stack::new();
+        for _ in 0..count {
+            let y = read_u32(&mut decoder)? as i32;
+            if version >= 3 {
+                let mut mask = [0u8; 2];
+                decoder.read_exact(&mut mask)?;
+                let mask = u16::from_le_bytes(mask);
+                for layer in (0..Section::HEIGHT).filter(|layer| mask & 1 << layer != 0) {
+                    let height = y * Section::HEIGHT + layer;
+                    if !Stack::in_height(height) {
+                        bail!("Slice at height {} is outside of the world", height);
+                    }
+                    stack.insert_slice(height, read_slice(&mut decoder)?);
+                }
+                continue;
+            }
+
+            if !Stack::in_height(y) {
+                bail!("Slice at height {} is outside of the world", y);
+            }
+            let slice = match version {
+                1 => {
+                    let mut blocks = vec![0u8; Slice::SIZE];
+                    decoder.read_exact(&mut blocks)?;
+                    Slice::from_blocks(&blocks).ok_or(anyhow!("Malformed slice"))?
+                },
+                _ => read_slice(&mut decoder)?
+            };
+            stack.insert_slice(y, slice);
+        }
+
+        Ok(stack)
+    }
+}
+
+fn write_slice(writer: &mut impl Write, slice: &Slice) -> anyhow::Result<()> {
+    writer.write_all(&[slice.bits() as u8, (slice.palette().len() - 1) as u8])?;
+    writer.write_all(slice.palette())?;
+    writer.write_all(slice.indices())?;
+    Ok(())
+}
+
+fn read_slice(reader: &mut impl Read) -> anyhow::Result<Slice> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let bits = header[0] as u32;
+    let mut palette = vec![0u8; header[1] as usize + 1];
+    reader.read_exact(&mut palette)?;
+    let mut indices = vec![0u8; Slice::SIZE * bits.min(8) as usize / 8];
+    reader.read_exact(&mut indices)?;
+    Slice::from_parts(palette, bits, indices).ok_or(anyhow!("Malformed slice"))
+}
+
+impl WorldBlocks {
+    // Writes every stack in memory into the region files of the world directory
+    pub fn save(&mut self, dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create world directory {}", dir.display()))?;
+
+        write_header(dir, self.seed())?;
+        fs::write(dir.join(PENDING_FILE), self.encode_pending()?)?;
+
+        let region_dir = dir.join(REGION_DIR);
+        let mut storage = match self.take_storage() {
+            Some(storage) if storage.dir() == region_dir => storage,
+            _ => RegionStore::open(&region_dir)?
+        };
+        let result = self.stacks()
+            .try_for_each(|(coords, stack)| storage.save_stack(coords, stack));
+        self.set_storage(storage);
+
+        result
+    }
+
+    // A new generated world that saves into the directory, stacks can then be unloaded as soon as the
+    // player leaves them. The directory must not exist yet, so no saved world is ever overwritten.
+    pub fn create(dir: &Path, registry: Arc<BlockRegistry>, seed: u64) -> anyhow::Result<Self> {
+        if dir.exists() {
+            bail!("{} already exists", dir.display());
+        }
+        let mut world = WorldBlocks::generated(registry, seed)?;
+        world.save(dir)?;
+        Ok(world)
+    }
+
+    // Opens a saved world, stacks are only read from disk once they are needed
+    pub fn load(dir: &Path, registry: Arc<BlockRegistry>) -> anyhow::Result<Self> {
+        let header = fs::read(dir.join(WORLD_FILE))
+            .with_context(|| format!("No world found at {}", dir.display()))?;
+        if header.len() < WORLD_MAGIC.len() + 2 || &header[..WORLD_MAGIC.len()] != WORLD_MAGIC {
+            bail!("{} is not a world file", dir.join(WORLD_FILE).display());
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        let seed = match version {
+            1 | 2 => LEGACY_SEED,
+            WORLD_FORMAT_VERSION => {
+                let seed = header.get(6..14).ok_or(anyhow!("World header is missing the seed"))?;
+                u64::from_le_bytes(seed.try_into().unwrap())
+            },
+            _ => bail!("Unsupported world format version {}", version)
+        };
+
+        let mut storage = RegionStore::open(&dir.join(REGION_DIR))?;
+        if version == 1 {
+            migrate_stack_files(dir, &mut storage)?;
+        }
+
+        let mut world = WorldBlocks::generated(registry, seed)?;
+        world.set_storage(storage);
+        // Worlds saved before decorations existed have nothing pending
+        if let Ok(data) = fs::read(dir.join(PENDING_FILE)) {
+            let pending = decode_pending(&data).context("Invalid pending decorations")?;
+            world.queue_writes(pending);
+        }
+        Ok(world)
+    }
+
+    fn encode_pending(&self) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        output.extend_from_slice(PENDING_MAGIC);
+        output.extend_from_slice(&PENDING_FORMAT_VERSION.to_le_bytes());
+
+        // Sorted so the same queue always encodes to the same bytes
+        let mut pending = self.pending_writes().collect::<Vec<_>>();
+        pending.sort_by_key(|(coords, _)| (coords.x, coords.z));
+
+        let mut encoder = ZlibEncoder::new(output, Compression::default());
+        encoder.write_all(&(pending.len() as u32).to_le_bytes())?;
+        for (coords, writes) in pending {
+            encoder.write_all(&coords.x.to_le_bytes())?;
+            encoder.write_all(&coords.z.to_le_bytes())?;
+            encoder.write_all(&(writes.len() as u32).to_le_bytes())?;
+            for write in writes {
+                encoder.write_all(&[write.local.x as u8])?;
+                encoder.write_all(&write.local.y.to_le_bytes())?;
+                encoder.write_all(&[write.local.z as u8, write.block])?;
+            }
+        }
+
+        Ok(encoder.finish()?)
+    }
+}
+
+fn write_header(dir: &Path, seed: u64) -> anyhow::Result<()> {
+    let mut header = Vec::new();
+    header.extend_from_slice(WORLD_MAGIC);
+    header.extend_from_slice(&WORLD_FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&seed.to_le_bytes());
+    fs::write(dir.join(WORLD_FILE), header)?;
+    Ok(())
+}
+
+// Moves the stack files of a version 1 world into region files. Every file is removed once its stack
+// is stored and the header is only rewritten at the end, so an interrupted migration resumes.
+fn migrate_stack_files(dir: &Path, storage: &mut RegionStore) -> anyhow::Result<()> {
+    let stack_dir = dir.join(STACK_DIR);
+    let mut count = 0;
+    for entry in fs::read_dir(&stack_dir)
+        .with_context(|| format!("Unable to read stack directory {}", stack_dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(STACK_EXTENSION) {
+            continue;
+        }
+
+        let coords = match parse_stack_name(&path) {
+            Some(coords) => coords,
+            None => {
+                log::warn!("Skipping unrecognised stack file {}", path.display());
+                continue
+            }
+        };
+        let stack = Stack::decode(&fs::read(&path)?)
+            .with_context(|| format!("Unable to read stack file {}", path.display()))?;
+        storage.save_stack(&coords, &stack)?;
+        fs::remove_file(&path)?;
+        count += 1;
+    }
+
+    write_header(dir, LEGACY_SEED)?;
+    if fs::remove_dir(&stack_dir).is_err() {
+        log::warn!("Leaving unrecognised files in {}", stack_dir.display());
+    }
+    log::info!("Moved {} stacks of {} into region files", count, dir.display());
+    Ok(())
+}
+
+fn parse_stack_name(path: &Path) -> Option<StackCoords> {
+    let (x, z) = path.file_stem()?.to_str()?.split_once('_')?;
+    Some(StackCoords { x: x.parse().ok()?, z: z.parse().ok()? })
+}
+
+fn decode_pending(data: &[u8]) -> anyhow::Result<Vec<(StackCoords, PendingWrite)>> {
+    if data.len() < PENDING_MAGIC.len() + 2 || &data[..PENDING_MAGIC.len()] != PENDING_MAGIC {
+        bail!("Not a pending decorations file");
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version != PENDING_FORMAT_VERSION {
+        bail!("Unsupported pending decorations format version {}", version);
+    }
+
+    let mut decoder = ZlibDecoder::new(&data[6..]);
+    let mut pending = Vec::new();
+    for _ in 0..read_u32(&mut decoder)? {
+        let coords = StackCoords { x: read_u32(&mut decoder)? as i32, z: read_u32(&mut decoder)? as i32 };
+        for _ in 0..read_u32(&mut decoder)? {
+            let mut write = [0u8; 7];
+            decoder.read_exact(&mut write)?;
+            let (x, y, z) = (write[0] as i32, i32::from_le_bytes(write[1..5].try_into().unwrap()), write[5] as i32);
+            if x >= Slice::X_SIZE || z >= Slice::Z_SIZE || !Stack::in_height(y) {
+                bail!("Pending block outside of its stack");
+            }
+            pending.push((coords, PendingWrite { local: BlockCoords::new(x, y, z), block: write[6] }));
+        }
+    }
+
+    Ok(pending)
+}
+
+fn read_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use crate::util::TestDir;
+    use super::*;
+
+    #[test]
+    fn sparse_stacks_round_trip() {
+        let mut stack = Stack::new();
+        for (i, y) in [-64, -63, -50, -1, 0, 15, 16, 100, 255].into_iter().enumerate() {
+            stack.set_block(BlockCoords::new(i as i32, y, 15 - i as i32), i as u8 + 1);
+        }
+        stack.insert_slice(40, Slice::new(9));
+
+        let decoded = Stack::decode(&stack.encode().unwrap()).unwrap();
+        let mut heights = decoded.slices().map(|(y, _)| y).collect::<Vec<_>>();
+        heights.sort();
+        assert_eq!(heights, [-64, -63, -50, -1, 0, 15, 16, 40, 100, 255]);
+        for (y, slice) in stack.slices() {
+            let copy = decoded.slice(y).unwrap();
+            assert_eq!((copy.palette(), copy.bits(), copy.indices()), (slice.palette(), slice.bits(), slice.indices()));
+        }
+        assert_eq!(decoded.encode().unwrap(), stack.encode().unwrap());
+    }
+
+    #[test]
+    fn decodes_version_2_stacks() {
+        // Version 2 wrote a count and then every slice with its height, there were no sections yet
+        let mut blob = [&STACK_MAGIC[..], &2u16.to_le_bytes()].concat();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&2u32.to_le_bytes()).unwrap();
+        encoder.write_all(&(-3i32).to_le_bytes()).unwrap();
+        encoder.write_all(&[0, 0, 4]).unwrap(); // Uniform
+        encoder.write_all(&70i32.to_le_bytes()).unwrap();
+        encoder.write_all(&[1, 1, 2, 6]).unwrap(); // Two blocks, the first byte of indices holds the first eight
+        encoder.write_all(&[0b0000_0010]).unwrap();
+        encoder.write_all(&[0; Slice::SIZE / 8 - 1]).unwrap();
+        blob.extend(encoder.finish().unwrap());
+
+        let stack = Stack::decode(&blob).unwrap();
+        assert_eq!(stack.slice(-3).unwrap().uniform(), Some(4));
+        assert_eq!(stack.get_block(BlockCoords::new(0, 70, 0)), 2);
+        assert_eq!(stack.get_block(BlockCoords::new(1, 70, 0)), 6);
+        assert_eq!(stack.get_block(BlockCoords::new(2, 70, 0)), 2);
+        assert_eq!(stack.slices().count(), 2);
+
+        blob[4] = 4;
+        assert!(Stack::decode(&blob).is_err());
+    }
+
+    #[test]
+    fn create_refuses_an_existing_directory() {
+        let dir = TestDir::new("create-existing");
+        fs::create_dir_all(dir.path.join("regions")).unwrap();
+        fs::write(dir.path.join("world.dat"), b"not a world").unwrap();
+
+        assert!(WorldBlocks::create(&dir.path, Arc::new(BlockRegistry::builtin()), 1).is_err());
+        assert_eq!(fs::read(dir.path.join("world.dat")).unwrap(), b"not a world");
+    }
+
+    // Version 1 stored a count and then every slice with its height and raw block ids
+    fn version_1_stack(blocks: &[(i32, u8)]) -> Vec<u8> {
+        let mut blob = [&STACK_MAGIC[..], &1u16.to_le_bytes()].concat();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&(blocks.len() as u32).to_le_bytes()).unwrap();
+        for (y, block) in blocks {
+            encoder.write_all(&y.to_le_bytes()).unwrap();
+            let mut slice = vec![0u8; Slice::SIZE];
+            slice[0] = *block;
+            encoder.write_all(&slice).unwrap();
+        }
+        blob.extend(encoder.finish().unwrap());
+        blob
+    }
+
+    #[test]
+    fn migrates_version_1_worlds_into_region_files() {
+        let dir = TestDir::new("load-v1");
+        let registry = Arc::new(BlockRegistry::builtin());
+        let stone = registry.id_of("stone").unwrap();
+        let dirt = registry.id_of("dirt").unwrap();
+
+        // A version 1 world is its header with a stack count and one file per stack
+        let stack_dir = dir.path.join(STACK_DIR);
+        fs::create_dir_all(&stack_dir).unwrap();
+        fs::write(stack_dir.join("0_0.stk"), version_1_stack(&[(3, stone), (-10, dirt)])).unwrap();
+        fs::write(stack_dir.join("-2_5.stk"), version_1_stack(&[(40, dirt)])).unwrap();
+        fs::write(stack_dir.join("notes.txt"), b"not a stack").unwrap();
+        fs::write(dir.path.join(WORLD_FILE), [&WORLD_MAGIC[..], &1u16.to_le_bytes(), &2u32.to_le_bytes()].concat())
+            .unwrap();
+
+        let mut world = WorldBlocks::load(&dir.path, registry.clone()).unwrap();
+        assert_eq!(world.seed(), LEGACY_SEED);
+        let left = fs::read_dir(&stack_dir).unwrap().map(|e| e.unwrap().file_name()).collect::<Vec<_>>();
+        assert_eq!(left, ["notes.txt"]);
+        let header = fs::read(dir.path.join(WORLD_FILE)).unwrap();
+        assert_eq!(u16::from_le_bytes([header[4], header[5]]), WORLD_FORMAT_VERSION);
+
+        world.fault_in(StackCoords { x: 0, z: 0 });
+        world.fault_in(StackCoords { x: -2, z: 5 });
+        assert_eq!(world.get_block(BlockCoords::new(0, 3, 0)), Some(stone));
+        assert_eq!(world.get_block(BlockCoords::new(0, -10, 0)), Some(dirt));
+        assert_eq!(world.get_block(BlockCoords::new(1, 3, 0)), Some(0));
+        assert_eq!(world.get_block(BlockCoords::new(-32, 40, 80)), Some(dirt));
+
+        // Loading again reads the region files
+        let mut world = WorldBlocks::load(&dir.path, registry).unwrap();
+        world.fault_in(StackCoords { x: -2, z: 5 });
+        assert_eq!(world.get_block(BlockCoords::new(-32, 40, 80)), Some(dirt));
+    }
+
+    #[test]
+    fn loads_version_2_worlds_with_the_legacy_seed() {
+        let dir = TestDir::new("load-v2");
+        let registry = Arc::new(BlockRegistry::builtin());
+        let stone = registry.id_of("stone").unwrap();
+        let coords = StackCoords { x: 2, z: -1 };
+
+        // A version 2 world is its header without a seed and its region files
+        let mut stack = Stack::new();
+        stack.set_block(BlockCoords::new(3, 5, 7), stone);
+        let mut regions = RegionStore::open(&dir.path.join(REGION_DIR)).unwrap();
+        regions.save_stack(&coords, &stack).unwrap();
+        drop(regions);
+        fs::write(dir.path.join(WORLD_FILE), [&WORLD_MAGIC[..], &2u16.to_le_bytes()].concat()).unwrap();
+
+        let mut world = WorldBlocks::load(&dir.path, registry.clone()).unwrap();
+        assert_eq!(world.seed(), LEGACY_SEED);
+        world.fault_in(coords);
+        assert_eq!(world.get_block(BlockCoords::new(2 * 16 + 3, 5, -16 + 7)), Some(stone));
+
+        // Saving moves it to the current header
+        world.save(&dir.path).unwrap();
+        let header = fs::read(dir.path.join(WORLD_FILE)).unwrap();
+        assert_eq!(u16::from_le_bytes([header[4], header[5]]), WORLD_FORMAT_VERSION);
+        assert_eq!(WorldBlocks::load(&dir.path, registry).unwrap().seed(), LEGACY_SEED);
+    }
+}
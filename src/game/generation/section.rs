@@ -0,0 +1,90 @@
+use std::mem;
+
+use super::slice::Slice;
+use super::super::blocks::BlockRegistry;
+use super::super::units::{ BlockCoords, BlockID };
+
+// A 16 block cube of a stack. Every layer is a slice, layers that are only air are left out.
+pub struct Section {
+    slices: [Option<Slice>; Self::HEIGHT as usize],
+}
+
+impl Section {
+    pub const HEIGHT: i32 = 16;
+
+    pub fn new() -> Self {
+        Self {
+            slices: Default::default(),
+        }
+    }
+
+    // Section and layer within it of a height, negative heights belong to negative sections
+    pub fn split_height(y: i32) -> (i32, i32) {
+        (y.div_euclid(Self::HEIGHT), y.rem_euclid(Self::HEIGHT))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slices.iter().all(Option::is_none)
+    }
+
+    pub fn slice(&self, layer: i32) -> Option<&Slice> {
+        self.slices[layer as usize].as_ref()
+    }
+
+    // Every layer that holds blocks, lowest first
+    pub fn slices(&self) -> impl Iterator<Item = (i32, &Slice)> {
+        self.slices.iter()
+            .enumerate()
+            .filter_map(|(layer, slice)| slice.as_ref().map(|slice| (layer as i32, slice)))
+    }
+
+    pub fn insert_slice(&mut self, layer: i32, slice: Slice) {
+        self.slices[layer as usize] = Some(slice);
+    }
+
+    // Position is local to the stack, with y the layer
+    pub fn get_block(&self, position: BlockCoords) -> BlockID {
+        match self.slice(position.y) {
+            Some(slice) => slice.get(position),
+            None => BlockRegistry::AIR
+        }
+    }
+
+    pub fn set_block(&mut self, position: BlockCoords, id: BlockID) {
+        match &mut self.slices[position.y as usize] {
+            Some(slice) => slice.set_block(position, id),
+            None if id == BlockRegistry::AIR => {}, // Missing slices are air already
+            slot => slot.insert(Slice::new(BlockRegistry::AIR)).set_block(position, id)
+        }
+    }
+
+    // Narrows every slice's palette to the blocks it still holds and drops slices that are only air
+    pub fn compact(&mut self) {
+        for slot in &mut self.slices {
+            if let Some(slice) = slot {
+                slice.compact();
+                if slice.uniform() == Some(BlockRegistry::AIR) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>() + self.slices().map(|(_, slice)| slice.memory_footprint() - mem::size_of::<Slice>()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heights_split_into_sections_and_layers() {
+        for (y, expected) in [(-64, (-4, 0)), (-17, (-2, 15)), (-16, (-1, 0)), (-1, (-1, 15)), (0, (0, 0)), (255, (15, 15))] {
+            let (section, layer) = Section::split_height(y);
+            assert_eq!((section, layer), expected, "{}", y);
+            assert_eq!(section * Section::HEIGHT + layer, y);
+        }
+    }
+}
@@ -1,61 +1,310 @@
 use std::collections::HashMap;
+use std::mem;
 
-use cgmath::{Point3, Vector2};
+use cgmath::EuclideanSpace;
 
-use crate::graphics::cube_render::cube_instance::CubeInstance;
+use super::super::coords;
 use super::super::units::{BlockCoords, BlockID};
 
+// Blocks are stored as indices into a palette of the blocks the slice holds, packed as tightly as the
+// palette size allows. A slice of a single block has no indices at all, which covers most of the
+// air and stone in a world.
 pub struct Slice {
-    blocks: Box<[BlockID; Self::SIZE]>
+    palette: Vec<BlockID>,
+    bits: u32, // Per index, one of INDEX_BITS
+    indices: Box<[u8]>, // Lowest bits first, empty while the slice is uniform
 }
 
 impl Slice {
     pub const X_SIZE: i32  = 16;
     pub const Z_SIZE: i32 = 16;
     pub const SIZE: usize = (Self::X_SIZE * Self::Z_SIZE) as usize;
+    pub const INDEX_BITS: [u32; 5] = [0, 1, 2, 4, 8]; // Powers of two so indices never straddle a byte
 
     pub fn new(id: BlockID) -> Self {
-        let blocks = Box::new([id; Self::SIZE]);
-
         Self {
-            blocks
+            palette: vec![id],
+            bits: 0,
+            indices: Box::new([]),
+        }
+    }
+
+    // From one block id per position, in slice index order
+    pub fn from_blocks(blocks: &[BlockID]) -> Option<Self> {
+        if blocks.len() != Self::SIZE {
+            return None;
+        }
+
+        let mut slice = Self::new(blocks[0]);
+        for (i, id) in blocks.iter().copied().enumerate() {
+            slice.set_index(i, id);
+        }
+        Some(slice)
+    }
+
+    // Rebuilds a slice from its palette, index width and packed indices, None if they do not fit together
+    pub fn from_parts(palette: Vec<BlockID>, bits: u32, indices: Vec<u8>) -> Option<Self> {
+        if !Self::INDEX_BITS.contains(&bits) || palette.is_empty() || palette.len() > 1 << bits
+            || indices.len() != Self::packed_len(bits) {
+            return None;
+        }
+
+        let slice = Self { palette, bits, indices: indices.into_boxed_slice() };
+        (0..Self::SIZE).all(|i| slice.index(i) < slice.palette.len()).then_some(slice)
+    }
+
+    pub fn palette(&self) -> &[BlockID] {
+        &self.palette
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn indices(&self) -> &[u8] {
+        &self.indices
+    }
+
+    // The block filling the whole slice, if it is known to be uniform
+    pub fn uniform(&self) -> Option<BlockID> {
+        match self.bits {
+            0 => Some(self.palette[0]),
+            _ => None
         }
     }
 
-    // Vector2 represents stack on its own
-    // Increases x, then z
-    // Every z contains Self::XSIZE number of x values
+    // Bytes held by the slice, including the slice itself
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>() + self.palette.capacity() * mem::size_of::<BlockID>() + self.indices.len()
+    }
+
+    // Position must be local to the stack, see coords::local_to_slice_index for the layout
     pub fn get(&self, position: BlockCoords) -> BlockID {
-        self.blocks[Self::coords_to_array_pos(position)]
+        self.palette[self.index(coords::local_to_slice_index(position))]
     }
 
     pub fn set_block(&mut self, position: BlockCoords, id: BlockID) {
-        self.blocks[Self::coords_to_array_pos(position)] = id;
+        self.set_index(coords::local_to_slice_index(position), id);
     }
 
     pub fn get_all_hash(&self, map: &mut HashMap<BlockCoords, BlockID>, offset: BlockCoords) {
-        for (i, block) in (0i32..).zip(self.blocks.iter().copied()) {
-            map.insert(Point3 {
-                x: (offset.x + (i % Self::X_SIZE)),
-                y: offset.y,
-                z: (offset.z + (i / Self::X_SIZE)) 
-            }, block);
+        for i in 0..Self::SIZE {
+            map.insert(offset + coords::slice_index_to_local(i, 0).to_vec(), self.palette[self.index(i)]);
+        }
+    }
+
+    // Drops palette entries nothing refers to anymore and narrows the indices if that makes them fit
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..Self::SIZE {
+            used[self.index(i)] = true;
+        }
+        if used.iter().all(|u| *u) {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (entry, id) in self.palette.iter().copied().enumerate() {
+            if used[entry] {
+                remap[entry] = palette.len();
+                palette.push(id);
+            }
         }
+
+        self.repack(Self::bits_for(palette.len()), |entry| remap[entry]);
+        self.palette = palette;
     }
 
-    pub fn get_all(&self, storage: &mut Vec<CubeInstance>, offset: BlockCoords) {
-        for (i, block) in (0i32..).zip(self.blocks.iter().copied()) {
-            if block == 0 { continue } // Just air, no need for rendering
+    fn set_index(&mut self, i: usize, id: BlockID) {
+        let entry = match self.palette.iter().position(|b| *b == id) {
+            Some(entry) => entry,
+            None => {
+                // Only widen the indices when making room in the palette is not enough
+                if self.palette.len() == 1 << self.bits {
+                    self.compact();
+                }
+                if self.palette.len() == 1 << self.bits {
+                    self.repack(Self::bits_for(self.palette.len() + 1), |entry| entry);
+                }
+                self.palette.push(id);
+                self.palette.len() - 1
+            }
+        };
+        self.write_index(i, entry);
+    }
 
-            storage.push(CubeInstance{ tex_index: (block as u32) - 1, position: Point3 {
-                x: (offset.x + (i % Self::X_SIZE)) as f32,
-                y: offset.y as f32,
-                z: (offset.z + (i / Self::X_SIZE)) as f32 
-            }});
+    fn index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
         }
+        let per_byte = (8 / self.bits) as usize;
+        let shift = (i % per_byte) as u32 * self.bits;
+        ((self.indices[i / per_byte] as u32 >> shift) & ((1 << self.bits) - 1)) as usize
     }
 
-    pub fn coords_to_array_pos(pos: BlockCoords) -> usize {
-        return (pos.x + pos.z * Self::X_SIZE) as usize;
+    fn write_index(&mut self, i: usize, entry: usize) {
+        if self.bits == 0 {
+            return;
+        }
+        let per_byte = (8 / self.bits) as usize;
+        let shift = (i % per_byte) as u32 * self.bits;
+        let mask = ((1u32 << self.bits) - 1) << shift;
+        let byte = &mut self.indices[i / per_byte];
+        *byte = ((*byte as u32 & !mask) | ((entry as u32) << shift)) as u8;
     }
-}
\ No newline at end of file
+
+    // Rewrites every index with a new width, passing each through the mapping
+    fn repack(&mut self, bits: u32, map: impl Fn(usize) -> usize) {
+        let entries = (0..Self::SIZE).map(|i| map(self.index(i))).collect::<Vec<_>>();
+        self.bits = bits;
+        self.indices = vec![0; Self::packed_len(bits)].into_boxed_slice();
+        for (i, entry) in entries.into_iter().enumerate() {
+            self.write_index(i, entry);
+        }
+    }
+
+    fn bits_for(palette_len: usize) -> u32 {
+        Self::INDEX_BITS.into_iter()
+            .find(|bits| 1 << bits >= palette_len)
+            .expect("A slice cannot hold more than 256 different blocks")
+    }
+
+    fn packed_len(bits: u32) -> usize {
+        Self::SIZE * bits as usize / 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::terrain::{ NoiseTerrain, TerrainGenerator };
+    use super::super::super::blocks::BlockRegistry;
+    use super::super::super::units::StackCoords;
+
+    fn blocks(slice: &Slice) -> Vec<BlockID> {
+        (0..Slice::SIZE).map(|i| slice.get(coords::slice_index_to_local(i, 0))).collect()
+    }
+
+    fn local(i: usize) -> BlockCoords {
+        coords::slice_index_to_local(i, 0)
+    }
+
+    #[test]
+    fn palette_widens_as_blocks_are_added() {
+        let mut slice = Slice::new(0);
+        let mut expected = vec![0; Slice::SIZE];
+        assert_eq!(slice.bits(), 0);
+
+        // 2, 3, 5 and 17 different blocks need the next index width
+        for id in 1..=16u8 {
+            let i = id as usize * 13;
+            slice.set_block(local(i), id);
+            expected[i] = id;
+
+            let bits = match slice.palette().len() {
+                2 => 1,
+                3..=4 => 2,
+                5..=16 => 4,
+                _ => 8
+            };
+            assert_eq!(slice.bits(), bits, "{} blocks", slice.palette().len());
+            assert_eq!(blocks(&slice), expected);
+        }
+        assert_eq!(slice.palette().len(), 17);
+        assert_eq!(slice.indices().len(), Slice::SIZE);
+    }
+
+    #[test]
+    fn every_block_id_fits_in_eight_bits() {
+        let ids = (0..Slice::SIZE).map(|i| i as BlockID).collect::<Vec<_>>();
+        let mut slice = Slice::from_blocks(&ids).unwrap();
+        assert_eq!((slice.bits(), slice.palette().len()), (8, 256));
+        assert_eq!(blocks(&slice), ids);
+
+        // A full palette makes room for a block by dropping entries nothing uses anymore
+        slice.set_block(local(0), 1);
+        slice.set_block(local(2), 0);
+        assert_eq!(slice.get(local(0)), 1);
+        assert_eq!(slice.get(local(2)), 0);
+        assert_eq!(slice.bits(), 8);
+    }
+
+    #[test]
+    fn full_palettes_are_compacted_before_widening() {
+        let mut slice = Slice::new(1);
+        slice.set_block(local(5), 2);
+        assert_eq!(slice.bits(), 1);
+
+        slice.set_block(local(5), 1);
+        slice.set_block(local(9), 3);
+        assert_eq!(slice.bits(), 1);
+        assert_eq!(slice.palette(), &[1, 3]);
+        assert_eq!((slice.get(local(5)), slice.get(local(9))), (1, 3));
+    }
+
+    #[test]
+    fn compacting_narrows_the_indices() {
+        let ids = (0..Slice::SIZE).map(|i| (i % 20) as BlockID).collect::<Vec<_>>();
+        let mut slice = Slice::from_blocks(&ids).unwrap();
+        assert_eq!(slice.bits(), 8);
+
+        for i in 0..Slice::SIZE {
+            slice.set_block(local(i), if i % 3 == 0 { 7 } else { 4 });
+        }
+        let expected = blocks(&slice);
+        slice.compact();
+        assert_eq!((slice.bits(), slice.palette().len()), (1, 2));
+        assert_eq!(blocks(&slice), expected);
+
+        for i in 0..Slice::SIZE {
+            slice.set_block(local(i), 4);
+        }
+        slice.compact();
+        assert_eq!(slice.uniform(), Some(4));
+        assert!(slice.indices().is_empty());
+    }
+
+    #[test]
+    fn parts_round_trip() {
+        for palette_size in [1, 2, 3, 16, 17, 256] {
+            let ids = (0..Slice::SIZE).map(|i| ((i * 7) % palette_size) as BlockID).collect::<Vec<_>>();
+            let slice = Slice::from_blocks(&ids).unwrap();
+            let copy = Slice::from_parts(slice.palette().to_vec(), slice.bits(), slice.indices().to_vec()).unwrap();
+            assert_eq!(blocks(&copy), ids);
+            assert_eq!(copy.bits(), slice.bits());
+        }
+    }
+
+    #[test]
+    fn parts_that_do_not_fit_are_rejected() {
+        let packed = |bits| vec![0; Slice::packed_len(bits)];
+        assert!(Slice::from_parts(vec![1], 0, packed(0)).is_some());
+        assert!(Slice::from_parts(vec![1, 2], 3, vec![0; 96]).is_none()); // Not one of INDEX_BITS
+        assert!(Slice::from_parts(vec![], 0, packed(0)).is_none());
+        assert!(Slice::from_parts(vec![1, 2, 3], 1, packed(1)).is_none()); // Palette larger than the indices reach
+        assert!(Slice::from_parts(vec![1, 2], 1, packed(2)).is_none());
+        assert!(Slice::from_parts(vec![1, 2, 3], 2, vec![0b11; Slice::packed_len(2)]).is_none()); // Index past the palette
+    }
+
+    #[test]
+    fn generated_terrain_is_packed_tightly() {
+        let registry = BlockRegistry::builtin();
+        let terrain = NoiseTerrain::new(3, &registry).unwrap();
+
+        let (mut slices, mut packed) = (0, 0);
+        for x in 0..16 {
+            for z in 0..16 {
+                let stack = terrain.generate(StackCoords { x, z }).stack;
+                for (_, slice) in stack.slices() {
+                    slices += 1;
+                    packed += slice.memory_footprint();
+                }
+            }
+        }
+
+        // The same slices as plain arrays of one id per block
+        let full = slices * (Slice::SIZE * mem::size_of::<BlockID>() + mem::size_of::<Box<[BlockID]>>());
+        assert!(packed * 2 < full, "{} slices take {} bytes, {} bytes as full arrays", slices, packed, full);
+    }
+}
@@ -1,80 +1,160 @@
 use std::collections::{ HashMap };
+use std::mem;
 
-use crate::game::generation::worldblocks::WorldBlocks;
-use crate::game::units::WorldCoords;
-use crate::graphics::cube_render::cube_instance::CubeInstance;
+use super::section::Section;
 use super::slice::Slice;
 use super::active_block::ActiveBlock;
-use super::super::units::{ BlockCoords, Loc, StackCoords, EntityCoords, BlockID };
+use super::super::blocks::BlockRegistry;
+use super::super::units::{ BlockCoords, Loc, BlockID };
 
 pub struct Stack {
-    pub slices: HashMap<Loc, Slice>,
-    active_blocks: HashMap<BlockCoords, ActiveBlock>
+    sections: HashMap<Loc, Section>, // Sections without any blocks are left out
+    #[allow(dead_code)] // No block keeps state yet
+    active_blocks: HashMap<BlockCoords, ActiveBlock>,
+    dirty: bool, // The blocks changed since the stack was last meshed
 }
 
 impl Stack {
-    pub const MAX_HEIGHT: i32 = 32;
+    // Blocks exist from MIN_HEIGHT up to but not including MAX_HEIGHT
+    pub const MIN_HEIGHT: i32 = -64;
+    pub const MAX_HEIGHT: i32 = 256;
 
     pub fn new() -> Self {
-        let slices = HashMap::new();
+        let sections = HashMap::new();
         let active_blocks = HashMap::new();
 
         Self {
-            slices,
-            active_blocks
+            sections,
+            active_blocks,
+            dirty: true,
         }
     }
 
-    pub fn test_layout() -> Self {
+    pub fn test_layout(id: BlockID) -> Self {
         let mut stack  = Stack::new();
         
         for i in 0i32..3i32 {
-            stack.slices.insert(i, Slice::new(1));
+            stack.insert_slice(i, Slice::new(id));
         };
 
         stack
     }
 
+    pub fn in_height(y: i32) -> bool {
+        (Self::MIN_HEIGHT..Self::MAX_HEIGHT).contains(&y)
+    }
+
     // Must be local stack coords!
     pub fn get_block(&self, loc: BlockCoords) -> BlockID {
-        match self.slices.get(&loc.y) {
-            Some(slice) => slice.get(loc),
-            None => 0
+        let (section, layer) = Section::split_height(loc.y);
+        match self.sections.get(&section) {
+            Some(section) => section.get_block(BlockCoords { y: layer, ..loc }),
+            None => BlockRegistry::AIR
         }
     }
 
+    // Blocks outside of the world's height are dropped, setting a block to what it already is changes nothing
     pub fn set_block(&mut self, position: BlockCoords, id: BlockID) {
-        let slice = match self.slices.get_mut(&position.y) {
-            Some(slice) => slice,
-            None => {
-                let slice = Slice::new(0);
-                self.slices.insert(position.y, slice);
-                self.slices.get_mut(&position.y).unwrap()
-            }
+        if !Self::in_height(position.y) || self.get_block(position) == id {
+            return;
+        }
+        let (section, layer) = Section::split_height(position.y);
+        let section = match self.sections.get_mut(&section) {
+            Some(section) => section,
+            None if id == BlockRegistry::AIR => return, // Missing sections are air already
+            None => self.sections.entry(section).or_insert_with(Section::new)
         };
 
-        slice.set_block(position, id);
+        section.set_block(BlockCoords { y: layer, ..position }, id);
+        self.dirty = true;
     }
 
-    pub fn all_blocks(&self, storage: &mut Vec<CubeInstance>, coords: StackCoords) {
-        let offset = Self::from_stack_coords(&coords);
-        for (y, slice) in &self.slices {
-            slice.get_all(storage, BlockCoords {
-                x: offset.0,
-                y: *y,
-                z: offset.1
-            });
-        };
+    pub fn slice(&self, y: i32) -> Option<&Slice> {
+        let (section, layer) = Section::split_height(y);
+        self.sections.get(&section).and_then(|section| section.slice(layer))
+    }
+
+    // Every slice that holds blocks with its height, in no particular order
+    pub fn slices(&self) -> impl Iterator<Item = (i32, &Slice)> {
+        self.sections.iter().flat_map(|(y, section)| {
+            section.slices().map(move |(layer, slice)| (y * Section::HEIGHT + layer, slice))
+        })
+    }
+
+    pub fn insert_slice(&mut self, y: i32, slice: Slice) {
+        let (section, layer) = Section::split_height(y);
+        self.sections.entry(section).or_insert_with(Section::new).insert_slice(layer, slice);
+        self.dirty = true;
+    }
+
+    // Every section with its index, section n holds the heights from n * Section::HEIGHT up
+    pub fn sections(&self) -> impl Iterator<Item = (i32, &Section)> {
+        self.sections.iter().map(|(y, section)| (*y, section))
+    }
+
+    // Narrows every slice's palette to the blocks it still holds and drops slices and sections that are only air
+    pub fn compact(&mut self) {
+        self.sections.values_mut().for_each(Section::compact);
+        self.sections.retain(|_, section| !section.is_empty());
+    }
+
+    // Bytes held by the blocks of the stack, leaving out the hash map's own bookkeeping
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.sections.capacity() * mem::size_of::<Loc>()
+            + self.sections.values().map(Section::memory_footprint).sum::<usize>()
     }
 
-    pub fn to_stack_coords(position: &WorldCoords) -> StackCoords {
-        StackCoords {
-            x: (position.x / Slice::X_SIZE as f32).floor() as i32,
-            z: (position.z / Slice::Z_SIZE as f32).floor() as i32,
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // For changes outside of the stack that still affect its mesh, like a neighbour's border blocks
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_reach_from_the_lowest_to_the_highest_height() {
+        let mut stack = Stack::new();
+        for y in [Stack::MIN_HEIGHT, -17, -16, -1, 0, Stack::MAX_HEIGHT - 1] {
+            stack.set_block(BlockCoords::new(3, y, 4), 5);
+            assert_eq!(stack.get_block(BlockCoords::new(3, y, 4)), 5, "{}", y);
+            assert_eq!(stack.get_block(BlockCoords::new(4, y, 3)), BlockRegistry::AIR);
         }
+        assert_eq!(stack.slices().count(), 6);
+    }
+
+    #[test]
+    fn setting_a_block_to_itself_keeps_the_stack_clean() {
+        let mut stack = Stack::test_layout(2);
+        stack.clear_dirty();
+        stack.set_block(BlockCoords::new(1, 1, 1), 2);
+        stack.set_block(BlockCoords::new(1, 20, 1), BlockRegistry::AIR);
+        assert!(!stack.is_dirty());
+
+        stack.set_block(BlockCoords::new(1, 1, 1), 3);
+        assert!(stack.is_dirty());
     }
 
-    pub fn from_stack_coords(position: &StackCoords) -> (i32, i32) {
-        (position.x * Slice::X_SIZE as i32, position.z * Slice::Z_SIZE as i32)
+    #[test]
+    fn blocks_outside_of_the_world_are_dropped() {
+        let mut stack = Stack::new();
+        stack.clear_dirty();
+        stack.set_block(BlockCoords::new(0, Stack::MAX_HEIGHT, 0), 5);
+        stack.set_block(BlockCoords::new(0, Stack::MIN_HEIGHT - 1, 0), 5);
+
+        assert_eq!(stack.get_block(BlockCoords::new(0, Stack::MAX_HEIGHT, 0)), BlockRegistry::AIR);
+        assert_eq!(stack.sections().count(), 0);
+        assert!(!stack.is_dirty());
     }
-}
\ No newline at end of file
+}
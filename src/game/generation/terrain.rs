@@ -0,0 +1,207 @@
+use anyhow::anyhow;
+
+use super::biome::Biome;
+use super::caves::CaveCarver;
+use super::decoration::{ Decorator, PendingWrite };
+use super::ores::{ OreScatter, OreTable };
+use super::noise::{ self, FractalNoise };
+use super::slice::Slice;
+use super::stack::Stack;
+use super::super::blocks::BlockRegistry;
+use super::super::coords;
+use super::super::units::{ BlockCoords, BlockID, StackCoords };
+
+// Fills in stacks that have never been saved. Must only depend on its own settings and the coordinates,
+// stacks are generated in any order and the same stack has to come out the same every time.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, coords: StackCoords) -> GeneratedStack;
+
+    fn biome_at(&self, x: i32, z: i32) -> Biome;
+
+    // Height of the highest ground block in the column, decorations and caves are not included
+    fn height_at(&self, x: i32, z: i32) -> i32;
+}
+
+pub struct GeneratedStack {
+    pub stack: Stack,
+    pub overflow: Vec<(StackCoords, PendingWrite)>, // Parts of decorations that reach into other stacks
+}
+
+// Blocks the generator places, looked up by name once
+struct TerrainBlocks {
+    bedrock: BlockID,
+    stone: BlockID,
+    sand: BlockID,
+    water: BlockID,
+    snow: BlockID,
+}
+
+struct BiomeBlocks {
+    surface: BlockID,
+    filler: BlockID,
+}
+
+// Hills from a fractal noise heightmap shaped by biomes, filler on stone under each biome's surface,
+// with sand beaches and water filling everything below sea level
+pub struct NoiseTerrain {
+    height: FractalNoise,
+    climate: [FractalNoise; 3], // Temperature, humidity, continentalness
+    caves: CaveCarver,
+    ores: OreScatter,
+    decorator: Decorator,
+    blocks: TerrainBlocks,
+    biome_blocks: Vec<BiomeBlocks>, // Indexed by Biome
+}
+
+impl NoiseTerrain {
+    pub const SEA_LEVEL: i32 = 8;
+    const HEIGHT_OCTAVES: u32 = 4;
+    const HEIGHT_FREQUENCY: f64 = 1. / 96.;
+    const CLIMATE_OCTAVES: u32 = 3;
+    const CLIMATE_FREQUENCY: f64 = 1. / 512.;
+    const CLIMATE_SCALE: f64 = 2.; // Fractal noise rarely leaves [-0.5, 0.5], spread it over the whole climate range
+    const BLEND_WIDTH: f64 = 0.25; // How far apart in climate two biomes still mix their heights
+    const FILLER_DEPTH: i32 = 3;
+    const BEACH_HEIGHT: i32 = 1; // Surfaces up to this far above the sea are sand
+    const SNOW_LINE: i32 = 36;
+    pub const BEDROCK_LEVEL: i32 = -32; // Everything at and below this is bedrock
+
+    pub fn new(seed: u64, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let block = |name: &str| registry.id_of(name).ok_or(anyhow!("Terrain needs a {} block", name));
+        // Every noise gets its own seed so they do not mirror each other
+        let climate = |salt: u64| FractalNoise::new(noise::sub_seed(seed, salt), Self::CLIMATE_OCTAVES, Self::CLIMATE_FREQUENCY);
+
+        let biome_blocks = Biome::ALL.iter()
+            .map(|biome| {
+                let settings = biome.settings();
+                Ok(BiomeBlocks { surface: block(settings.surface)?, filler: block(settings.filler)? })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            height: FractalNoise::new(seed, Self::HEIGHT_OCTAVES, Self::HEIGHT_FREQUENCY),
+            climate: [climate(1), climate(2), climate(3)],
+            caves: CaveCarver::new(seed, block("water")?),
+            ores: OreScatter::new(seed, OreTable::load_default(registry)),
+            decorator: Decorator::new(seed, registry)?,
+            blocks: TerrainBlocks {
+                bedrock: block("bedrock")?,
+                stone: block("stone")?,
+                sand: block("sand")?,
+                water: block("water")?,
+                snow: block("snow")?,
+            },
+            biome_blocks,
+        })
+    }
+
+    fn climate_at(&self, x: i32, z: i32) -> [f64; 3] {
+        self.climate.map(|noise| (noise.get2(x as f64, z as f64) * Self::CLIMATE_SCALE).clamp(-1., 1.))
+    }
+
+    fn climate_distance(climate: [f64; 3], biome: Biome) -> f64 {
+        let center = biome.settings().climate;
+        climate.iter().zip(center).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt()
+    }
+
+    // The biome with the closest climate
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let climate = self.climate_at(x, z);
+        Biome::ALL.into_iter()
+            .min_by(|a, b| Self::climate_distance(climate, *a).total_cmp(&Self::climate_distance(climate, *b)))
+            .unwrap()
+    }
+
+    // Height of the highest solid block in the column. Every biome's shape is weighed by how close
+    // its climate is, so the ground changes gradually where biomes meet.
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let climate = self.climate_at(x, z);
+        let detail = self.height.get2(x as f64, z as f64);
+
+        let (mut height, mut total) = (0., 0.);
+        for biome in Biome::ALL {
+            let settings = biome.settings();
+            let weight = (-(Self::climate_distance(climate, biome) / Self::BLEND_WIDTH).powi(2)).exp();
+            height += (settings.base_height + detail * settings.height_variation) * weight;
+            total += weight;
+        }
+
+        // Far from every biome all weights vanish, fall back to the closest
+        let height = if total > f64::EPSILON {
+            height / total
+        } else {
+            let settings = self.biome_at(x, z).settings();
+            settings.base_height + detail * settings.height_variation
+        };
+        (height.round() as i32).clamp(Self::BEDROCK_LEVEL + 1, Stack::MAX_HEIGHT - 1)
+    }
+
+    fn block_at(&self, y: i32, surface: i32, biome: Biome) -> BlockID {
+        let beach = biome != Biome::Ocean && surface <= Self::SEA_LEVEL + Self::BEACH_HEIGHT;
+        let blocks = &self.biome_blocks[biome as usize];
+        if y <= Self::BEDROCK_LEVEL {
+            self.blocks.bedrock
+        } else if y > surface {
+            self.blocks.water
+        } else if y == surface {
+            if beach { self.blocks.sand } else if surface >= Self::SNOW_LINE { self.blocks.snow } else { blocks.surface }
+        } else if y > surface - Self::FILLER_DEPTH {
+            if beach { self.blocks.sand } else { blocks.filler }
+        } else {
+            self.blocks.stone
+        }
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn generate(&self, coords: StackCoords) -> GeneratedStack {
+        let mut stack = Stack::new();
+
+        for x in 0..Slice::X_SIZE {
+            for z in 0..Slice::Z_SIZE {
+                let block = coords::local_to_block(coords, BlockCoords::new(x, 0, z));
+                let (surface, biome) = (self.height_at(block.x, block.z), self.biome_at(block.x, block.z));
+
+                for y in Self::BEDROCK_LEVEL..=surface.max(Self::SEA_LEVEL) {
+                    stack.set_block(BlockCoords::new(x, y, z), self.block_at(y, surface, biome));
+                }
+            }
+        }
+
+        self.caves.carve(coords, &mut stack);
+        self.ores.place(coords, &mut stack);
+        let overflow = self.decorator.decorate(coords, &mut stack, |x, z| (self.height_at(x, z), self.biome_at(x, z)));
+        stack.compact();
+        GeneratedStack { stack, overflow }
+    }
+
+    fn biome_at(&self, x: i32, z: i32) -> Biome {
+        NoiseTerrain::biome_at(self, x, z)
+    }
+
+    fn height_at(&self, x: i32, z: i32) -> i32 {
+        NoiseTerrain::height_at(self, x, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_is_deterministic() {
+        let registry = BlockRegistry::builtin();
+        let coords = StackCoords { x: 5, z: -3 };
+        let first = NoiseTerrain::new(42, &registry).unwrap().generate(coords);
+        let second = NoiseTerrain::new(42, &registry).unwrap().generate(coords);
+
+        assert_eq!(first.stack.encode().unwrap(), second.stack.encode().unwrap());
+        let overflow = |generated: &GeneratedStack| generated.overflow.iter()
+            .map(|(coords, write)| (coords.x, coords.z, write.local, write.block))
+            .collect::<Vec<_>>();
+        assert_eq!(overflow(&first), overflow(&second));
+
+        let other = NoiseTerrain::new(43, &registry).unwrap().generate(coords);
+        assert_ne!(first.stack.encode().unwrap(), other.stack.encode().unwrap());
+    }
+}
@@ -1,116 +1,371 @@
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
+use std::sync::Arc;
 use cgmath::{ ElementWise, Vector3 };
 
 use crate::util::range3d;
 use crate::game::units::{BlockCoords, WorldCoords, BlockID};
-use crate::graphics::cube_render::cube_instance::CubeInstance;
-use super::stack::Stack;
+use crate::graphics::cube_render::stack_mesh::StackMesh;
 use super::slice::Slice;
-use super::super::units::{ StackCoords, EntityCoords, to_block_coord };
+use super::stack::Stack;
+use super::region::RegionStore;
+use super::mesher::{ Mesher, MeshingMode, StackNeighbours };
+use super::terrain::{ GeneratedStack, NoiseTerrain, TerrainGenerator };
+use super::jobs::JobPool;
+use super::biome::Biome;
+use super::decoration::{ FeaturePrecedence, PendingWrite };
+use super::super::units::{ StackCoords, EntityCoords };
+use super::super::blocks::BlockRegistry;
+use super::super::coords;
 use super::super::components::{ collision::BoxCollider, spatial::Position };
 
 pub struct WorldBlocks {
-    stacks: HashMap<StackCoords, Stack>
+    stacks: HashMap<StackCoords, Stack>,
+    storage: Option<RegionStore>,
+    registry: Arc<BlockRegistry>,
+    generator: Option<Arc<dyn TerrainGenerator>>, // Stacks that are not on disk stay missing without one
+    jobs: Option<JobPool<GeneratedStack>>, // Generates stacks in the background, with the same generator
+    seed: u64,
+    meshing_mode: MeshingMode,
+    meshed: HashSet<StackCoords>, // Stacks the renderer currently holds a mesh for
+    pending: HashMap<StackCoords, Vec<PendingWrite>>, // Decoration blocks waiting for their stack to be generated or loaded
+    precedence: FeaturePrecedence,
 }
 
 impl WorldBlocks {
     pub const TOUCH_TOLERANCE: f32 = 0.2;
     pub const STACK_RENDER_BOUND: i32 = 3;
-    pub const BLOCK_RENDER_COUNT: i32 = Slice::X_SIZE * Slice::Z_SIZE * Stack::MAX_HEIGHT * Self::STACK_RENDER_BOUND * Self::STACK_RENDER_BOUND;
 
-    pub fn test_layout() -> Self {
+    pub fn new(registry: Arc<BlockRegistry>) -> Self {
+        Self {
+            stacks: HashMap::new(),
+            storage: None,
+            precedence: FeaturePrecedence::new(&registry),
+            registry,
+            generator: None,
+            jobs: None,
+            seed: 0,
+            meshing_mode: MeshingMode::Greedy,
+            meshed: HashSet::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    // Endless world generated from the seed
+    pub fn generated(registry: Arc<BlockRegistry>, seed: u64) -> anyhow::Result<Self> {
+        let generator: Arc<dyn TerrainGenerator> = Arc::new(NoiseTerrain::new(seed, &registry)?);
+        let worker_generator = generator.clone();
+        let mut world = Self::new(registry);
+        world.generator = Some(generator);
+        world.jobs = Some(JobPool::new("generation", move |coords| worker_generator.generate(coords)));
+        world.seed = seed;
+        Ok(world)
+    }
+
+    pub fn test_layout(registry: Arc<BlockRegistry>) -> Self {
         let mut stacks = HashMap::new();
+        let grass = registry.id_of("grass").expect("Test layout needs a grass block");
 
         for x in 0..3 {
             for z in 0..3 {
                 let coords = StackCoords { x, z };
-                stacks.insert(coords, Stack::test_layout());
+                stacks.insert(coords, Stack::test_layout(grass));
             }
         };
 
         Self {
-            stacks
+            stacks,
+            storage: None,
+            precedence: FeaturePrecedence::new(&registry),
+            registry,
+            generator: None,
+            jobs: None,
+            seed: 0,
+            meshing_mode: MeshingMode::Greedy,
+            meshed: HashSet::new(),
+            pending: HashMap::new(),
         }
     }
 
-    // Returns the stack coordinates, the block position within the stack, and the stack itself
-    pub fn get_stack_at(&self, position: BlockCoords) -> Option<(StackCoords, BlockCoords, &Stack)> {
-        let coords = StackCoords {
-            x: (position.x / Slice::X_SIZE),
-            z: (position.z / Slice::Z_SIZE),
-        };
-        let offset = BlockCoords {
-            x: position.x as i32 % Slice::X_SIZE,
-            y: position.y as i32,
-            z: position.z as i32 % Slice::Z_SIZE,
-        };
-        let stack = self.stacks.get(&coords);
-        match stack {
-            Some(stack) => return Some((coords, offset, stack)),
-            None => None,
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // None for worlds without a generator
+    pub fn biome_at(&self, x: i32, z: i32) -> Option<Biome> {
+        self.generator.as_ref().map(|g| g.biome_at(x, z))
+    }
+
+    // None for worlds without a generator
+    pub fn height_at(&self, x: i32, z: i32) -> Option<i32> {
+        self.generator.as_ref().map(|g| g.height_at(x, z))
+    }
+
+    pub fn meshing_mode(&self) -> MeshingMode {
+        self.meshing_mode
+    }
+
+    // Every stack is remeshed with the new mode
+    pub fn set_meshing_mode(&mut self, mode: MeshingMode) {
+        self.meshing_mode = mode;
+        self.stacks.values_mut().for_each(|s| s.mark_dirty());
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    // Decorations from neighbours that were generated first are finished before the stack is used
+    pub fn insert_stack(&mut self, coords: StackCoords, mut stack: Stack) {
+        for write in self.pending.remove(&coords).unwrap_or_default() {
+            write.apply(&mut stack, &self.precedence);
         }
+        self.stacks.insert(coords, stack);
+        self.mark_neighbours_dirty(coords);
     }
 
-    pub fn get_stack_at_mut(&mut self, position: BlockCoords) -> Option<(StackCoords, BlockCoords, &mut Stack)> {
-        let coords = StackCoords {
-            x: (position.x / Slice::X_SIZE),
-            z: (position.z / Slice::Z_SIZE),
+    pub fn pending_writes(&self) -> impl Iterator<Item = (&StackCoords, &Vec<PendingWrite>)> {
+        self.pending.iter()
+    }
+
+    // Writes into stacks that are in memory happen right away, the rest wait for their stack
+    pub fn queue_writes(&mut self, writes: impl IntoIterator<Item = (StackCoords, PendingWrite)>) {
+        for (coords, write) in writes {
+            match self.stacks.get_mut(&coords) {
+                Some(stack) => {
+                    if write.apply(stack, &self.precedence) {
+                        self.mark_border_dirty(coords, write.local);
+                    }
+                },
+                None => self.pending.entry(coords).or_default().push(write)
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, coords: StackCoords) {
+        if let Some(stack) = self.stacks.get_mut(&coords) {
+            stack.mark_dirty();
+        }
+    }
+
+    // Blocks on a border can hide or reveal faces of the neighbouring stack
+    fn mark_border_dirty(&mut self, coords: StackCoords, local: BlockCoords) {
+        if local.x == 0 {
+            self.mark_dirty(StackCoords { x: coords.x - 1, ..coords });
+        } else if local.x == Slice::X_SIZE - 1 {
+            self.mark_dirty(StackCoords { x: coords.x + 1, ..coords });
+        }
+        if local.z == 0 {
+            self.mark_dirty(StackCoords { z: coords.z - 1, ..coords });
+        } else if local.z == Slice::Z_SIZE - 1 {
+            self.mark_dirty(StackCoords { z: coords.z + 1, ..coords });
+        }
+    }
+
+    // Faces along the border of a new stack were drawn against air until now
+    fn mark_neighbours_dirty(&mut self, coords: StackCoords) {
+        for (dx, dz) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            self.mark_dirty(StackCoords { x: coords.x + dx, z: coords.z + dz });
+        }
+    }
+
+    pub fn stacks(&self) -> impl Iterator<Item = (&StackCoords, &Stack)> {
+        self.stacks.iter()
+    }
+
+    // Bytes held by the blocks of every stack in memory
+    pub fn memory_footprint(&self) -> usize {
+        self.stacks.values().map(Stack::memory_footprint).sum()
+    }
+
+    pub fn set_storage(&mut self, storage: RegionStore) {
+        self.storage = Some(storage);
+    }
+
+    pub fn take_storage(&mut self) -> Option<RegionStore> {
+        self.storage.take()
+    }
+
+    fn load_stored(&mut self, coords: StackCoords) -> anyhow::Result<Option<Stack>> {
+        match &mut self.storage {
+            Some(storage) => storage.load_stack(&coords),
+            None => Ok(None)
+        }
+    }
+
+    // Reads the stack from disk, or generates it on the spot if it was never saved, when it is not in memory yet.
+    // Returns whether the stack is now available.
+    pub fn fault_in(&mut self, coords: StackCoords) -> bool {
+        if self.stacks.contains_key(&coords) {
+            return true;
+        }
+
+        let (stack, overflow) = match self.load_stored(coords) {
+            Ok(Some(stack)) => (stack, Vec::new()),
+            Ok(None) => match &self.generator {
+                Some(generator) => {
+                    let generated = generator.generate(coords);
+                    (generated.stack, generated.overflow)
+                },
+                None => return false
+            },
+            Err(e) => {
+                // Generating over a damaged stack would overwrite it on the next save
+                log::error!("Unable to load stack {:?}: {:#}", coords, e);
+                return false;
+            }
         };
-        let offset = BlockCoords {
-            x: position.x as i32 % Slice::X_SIZE,
-            y: position.y as i32,
-            z: position.z as i32 % Slice::Z_SIZE,
+
+        // The workers' copy would arrive too late to be used
+        if let Some(jobs) = &self.jobs {
+            jobs.cancel(coords);
+        }
+        self.insert_stack(coords, stack);
+        self.queue_writes(overflow);
+        true
+    }
+
+    // Like fault_in, but stacks that need generating are left to the workers instead of waiting for them.
+    // Lower priorities are generated first. Returns whether the stack is available now.
+    pub fn request_stack(&mut self, coords: StackCoords, priority: i32) -> bool {
+        if self.stacks.contains_key(&coords) {
+            return true;
+        }
+        match &self.jobs {
+            Some(jobs) if jobs.is_pending(coords) => {
+                jobs.request(coords, priority);
+                return false;
+            },
+            Some(_) => {},
+            None => return self.fault_in(coords)
+        }
+
+        match self.load_stored(coords) {
+            Ok(Some(stack)) => {
+                self.insert_stack(coords, stack);
+                true
+            },
+            Ok(None) => {
+                if let Some(jobs) = &self.jobs {
+                    jobs.request(coords, priority);
+                }
+                false
+            },
+            Err(e) => {
+                log::error!("Unable to load stack {:?}: {:#}", coords, e);
+                false
+            }
+        }
+    }
+
+    // Stops generating every requested stack the predicate rejects
+    pub fn cancel_requests(&self, keep: impl FnMut(StackCoords) -> bool) {
+        if let Some(jobs) = &self.jobs {
+            jobs.retain(keep);
+        }
+    }
+
+    // Saves and drops every stack the predicate rejects. Without storage nothing is dropped, the stacks
+    // could not come back the way they were.
+    pub fn unload_where(&mut self, keep: impl Fn(StackCoords) -> bool) {
+        let storage = match &mut self.storage {
+            Some(storage) => storage,
+            None => return
         };
-        let stack = self.stacks.get_mut(&coords);
-        match stack {
-            Some(stack) => return Some((coords, offset, stack)),
-            None => None,
+
+        let mut unloaded = Vec::new();
+        for (coords, stack) in self.stacks.iter().filter(|(coords, _)| !keep(**coords)) {
+            match storage.save_stack(coords, stack) {
+                Ok(()) => unloaded.push(*coords),
+                Err(e) => log::error!("Unable to save stack {:?}, keeping it loaded: {:#}", coords, e)
+            }
+        }
+
+        for coords in unloaded {
+            self.stacks.remove(&coords);
+            self.meshed.remove(&coords);
+            // Border faces were hidden by this stack
+            self.mark_neighbours_dirty(coords);
         }
     }
 
-    pub fn get_block(&self, position: BlockCoords) -> Option<BlockID> {
-        match self.get_stack_at(position) {
-            Some((_, offset, stack)) => Some(stack.get_block(offset)),
-            None => None
+    // Takes in the stacks the workers finished since the last call
+    pub fn receive_generated(&mut self) {
+        let finished = match &self.jobs {
+            Some(jobs) => jobs.finished(),
+            None => return
+        };
+        for (coords, generated) in finished {
+            // Generated on the spot while the workers had it
+            if self.stacks.contains_key(&coords) {
+                continue;
+            }
+            self.insert_stack(coords, generated.stack);
+            self.queue_writes(generated.overflow);
         }
     }
 
+    // Returns the stack coordinates, the block position within the stack, and the stack itself.
+    // Stacks that are saved or can be generated are brought into memory first.
+    pub fn get_stack_at(&mut self, position: BlockCoords) -> Option<(StackCoords, BlockCoords, &Stack)> {
+        let (coords, offset) = coords::split_block(position);
+        self.fault_in(coords);
+        self.stacks.get(&coords).map(|stack| (coords, offset, stack))
+    }
+
+    pub fn get_stack_at_mut(&mut self, position: BlockCoords) -> Option<(StackCoords, BlockCoords, &mut Stack)> {
+        let (coords, offset) = coords::split_block(position);
+        self.fault_in(coords);
+        self.stacks.get_mut(&coords).map(|stack| (coords, offset, stack))
+    }
+
+    pub fn get_block(&mut self, position: BlockCoords) -> Option<BlockID> {
+        self.get_stack_at(position).map(|(_, offset, stack)| stack.get_block(offset))
+    }
+
+    // False if the stack is not loaded or the position is outside of the world's height
     pub fn set_block(&mut self, position: BlockCoords, id: BlockID) -> bool {
-        match self.get_stack_at_mut(position) {
-            Some((_, offset, stack)) => {
+        if !Stack::in_height(position.y) {
+            return false;
+        }
+        let (coords, offset) = match self.get_stack_at_mut(position) {
+            Some((_, offset, stack)) if stack.get_block(offset) == id => return true, // Nothing to remesh
+            Some((coords, offset, stack)) => {
                 stack.set_block(offset, id);
-                true
+                (coords, offset)
             }
-            None => false
-        }
+            None => return false
+        };
+
+        self.mark_border_dirty(coords, offset);
+        true
     }
 
     // Get a hashmap of coordinates and ids if a subset is small enough, for easier block checking
-    pub fn get_subset(&self, position: WorldCoords, bounds: Vector3<f32>) -> HashMap<BlockCoords, BlockID> {
+    pub fn get_subset(&mut self, position: WorldCoords, bounds: Vector3<f32>) -> HashMap<BlockCoords, BlockID> {
         // Determine which stacks
-        let mut stacks: HashMap<StackCoords, &Stack> = HashMap::new();
+        let mut stacks: HashSet<StackCoords> = HashSet::new();
         for x in [position.x - bounds.x, position.x + bounds.x] {
             for z in [position.z - bounds.z, position.z + bounds.z] {
-                match self.get_stack_at(to_block_coord(WorldCoords { x, y: 0., z})) {
-                    Some((coords, _, stack)) => { stacks.insert(coords, &stack); },
-                    None => {},
+                let coords = coords::world_to_stack(WorldCoords { x, y: 0., z });
+                if self.fault_in(coords) {
+                    stacks.insert(coords);
                 }
             }
         }
 
         let mut blocks: HashMap<BlockCoords, BlockID> = HashMap::new();
-        for (coords, stack) in stacks {
+        for (coords, stack) in stacks.into_iter().map(|coords| (coords, &self.stacks[&coords])) {
             for y in (position.y - bounds.y) as i32..(position.y + bounds.y) as i32 + 1 {
-                if let Some(slice) = stack.slices.get(&y) {
-                    let coords = BlockCoords { x: coords.x * Slice::X_SIZE, y, z: coords.z * Slice::Z_SIZE };
-                    slice.get_all_hash(&mut blocks, coords);
+                if let Some(slice) = stack.slice(y) {
+                    let origin = coords::stack_origin(coords);
+                    slice.get_all_hash(&mut blocks, BlockCoords { y, ..origin });
                 }
             }
         };
         blocks
     }
 
-    pub fn get_block_contact(&self, collider: &BoxCollider, position: &Position) -> Vec<(BlockID, Vector3<i32>, f32)> {
+    pub fn get_block_contact(&mut self, collider: &BoxCollider, position: &Position) -> Vec<(BlockID, Vector3<i32>, f32)> {
         let mut collisions = Vec::new();
         let blocks = self.get_subset(position.vector, collider.bounds); // Guarentees a possible position
         let (pos, bounds) = (position.vector, collider.bounds);
@@ -162,40 +417,94 @@ impl WorldBlocks {
         }
 
         // Now check individual blocks
-        // TODO: Replace with a map of blocks that can be walked through
         for (possible_blocks, direction, diff) in possibilities {
             for block in possible_blocks {
-                match blocks.get(&block) { // Our subset guarentees a block location
-                    Some(id_ref) => {
-                        let id = *id_ref;
-                        if id != 0 { // 0 is air block, can walk through it
-                            collisions.push((id, direction, diff))
-                        }
+                if let Some(id) = blocks.get(&block).copied() { // Our subset guarentees a block location
+                    if self.registry.is_solid(id) {
+                        collisions.push((id, direction, diff))
                     }
-                    None => {}
                 }
             }
         }
         collisions
     }
 
-    pub fn get_renderable_blocks(&mut self, position: EntityCoords) -> Vec<CubeInstance> {
-        // Render the 3x3 chunk area around player
-        let mut blocks = Vec::with_capacity(Self::BLOCK_RENDER_COUNT as usize);
-        let stackcoords = Stack::to_stack_coords(&position);
+    // Every loaded stack within render distance of the position, and new meshes for those of them
+    // that changed or that the renderer does not have yet. Stacks outside of the range are forgotten
+    // so they are meshed again once they come back. Loading is left to the chunk manager.
+    pub fn get_stack_meshes(&mut self, position: EntityCoords) -> (Vec<StackCoords>, Vec<(StackCoords, StackMesh)>) {
+        let mut visible = Vec::new();
+        let stackcoords = coords::world_to_stack(position);
 
         for x in (stackcoords.x - Self::STACK_RENDER_BOUND)..(stackcoords.x + Self::STACK_RENDER_BOUND) {
             for z in (stackcoords.z - Self::STACK_RENDER_BOUND)..(stackcoords.z + Self::STACK_RENDER_BOUND) {
                 let coords = StackCoords { x, z };
-                let stack = match self.stacks.get(&coords) {
-                    Some(stack) => stack,
-                    None => continue  // Don't render ungenerated chunks, later will trigger generation
-                };
-
-                stack.all_blocks(&mut blocks, coords);
+                if self.stacks.contains_key(&coords) {
+                    visible.push(coords);
+                }
             }
         };
 
-        blocks
+        let in_range = visible.iter().copied().collect::<HashSet<_>>();
+        self.meshed.retain(|c| in_range.contains(c));
+
+        let outdated = visible.iter()
+            .copied()
+            .filter(|c| self.stacks[c].is_dirty() || !self.meshed.contains(c))
+            .collect::<Vec<_>>();
+
+        let mut meshes = Vec::new();
+        for coords in outdated {
+            let (x, z) = (coords.x, coords.z);
+            let neighbours = StackNeighbours {
+                neg_x: self.stacks.get(&StackCoords { x: x - 1, z }),
+                pos_x: self.stacks.get(&StackCoords { x: x + 1, z }),
+                neg_z: self.stacks.get(&StackCoords { x, z: z - 1 }),
+                pos_z: self.stacks.get(&StackCoords { x, z: z + 1 }),
+            };
+            meshes.push((coords, Mesher::new(&self.stacks[&coords], neighbours, &self.registry, self.meshing_mode).mesh(coords)));
+
+            self.stacks.get_mut(&coords).unwrap().clear_dirty();
+            self.meshed.insert(coords);
+        }
+
+        (visible, meshes)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::TestDir;
+    use super::*;
+    use super::super::super::components::spatial::Direction;
+
+    #[test]
+    fn evicted_stacks_are_read_back_on_lookup() {
+        let dir = TestDir::new("evict");
+        let registry = Arc::new(BlockRegistry::builtin());
+        let stone = registry.id_of("stone").unwrap();
+        let mut world = WorldBlocks::test_layout(registry);
+        world.set_storage(RegionStore::open(&dir.path).unwrap());
+
+        let block = BlockCoords::new(20, 5, 4);
+        assert!(world.set_block(block, stone));
+        world.unload_where(|coords| coords != StackCoords { x: 1, z: 0 });
+        assert_eq!(world.stacks().count(), 8);
+
+        assert_eq!(world.get_block(block), Some(stone));
+        assert_eq!(world.stacks().count(), 9);
+
+        // Collision and raycasts see it too
+        world.unload_where(|coords| coords != StackCoords { x: 1, z: 0 });
+        let subset = world.get_subset(WorldCoords::new(20.5, 5.5, 4.5), Vector3::new(0.5, 0.5, 0.5));
+        assert_eq!(subset.get(&block), Some(&stone));
+
+        world.unload_where(|coords| coords != StackCoords { x: 1, z: 0 });
+        let hit = world.raycast(&Position { vector: WorldCoords::new(20.5, 10.5, 4.5) },
+                                &Direction { vector: Vector3::new(0., -1., 0.) }, 10.).unwrap();
+        assert_eq!((hit.block, hit.id), (block, stone));
+
+        // Stacks that were never saved are still missing without a generator
+        assert_eq!(world.get_block(BlockCoords::new(-1, 0, 0)), None);
+    }
+}
@@ -1,23 +1,33 @@
 pub mod generation;
-mod units;
+pub mod blocks;
+pub mod coords;
+pub mod units;
 mod player;
 pub mod components;
 pub mod renderables;
 
 use cgmath::{ Vector3, Vector4, Point3, Quaternion };
 use legion::{self, Schedule, IntoQuery};
-use std::time::Instant;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::time::{ Instant, SystemTime, UNIX_EPOCH };
 
 
 use renderables::Renderables;
 use generation::worldblocks::WorldBlocks;
+use generation::chunk_manager::ChunkManager;
+use blocks::BlockRegistry;
+use units::BlockCoords;
 use player::{ Camera };
 use crate::{application::Input};
+use crate::bindings::Action;
 use crate::graphics::text_render::{ text_style::TextStyle, sentence::Sentence };
-use components::{ time::Time, spatial::{ Direction, Position }};
+use components::{ time::Time, spatial::{ Direction, Position }, block_edit::BlockEditor };
 
 pub struct Game {
     blocks: WorldBlocks,
+    chunks: ChunkManager,
     world: legion::World,
     pre_collision_schedule: legion::Schedule,
     post_collision_schedule: legion::Schedule,
@@ -29,13 +39,41 @@ pub struct Game {
 
 
 impl Game {
+    pub const WORLD_DIR: &'static str = "worlds/default";
+
     pub fn new() -> Self {
-        let blocks = WorldBlocks::test_layout();
+        let registry = match BlockRegistry::load(Path::new(BlockRegistry::DEFAULT_PATH)) {
+            Ok(registry) => registry,
+            Err(e) => {
+                log::warn!("Using builtin block definitions: {:#}", e);
+                BlockRegistry::builtin()
+            }
+        };
+        let registry = Arc::new(registry);
+
+        let dir = Path::new(Self::WORLD_DIR);
+        let mut blocks = if dir.exists() {
+            match WorldBlocks::load(dir, registry.clone()) {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    // Never generate over a world that could not be read, keep it for recovery instead
+                    log::error!("Unable to load the world: {:#}", e);
+                    let kept = Self::move_aside(dir);
+                    log::warn!("Moved the unreadable world to {}, starting a new world", kept.display());
+                    Self::new_world(registry)
+                }
+            }
+        } else {
+            log::info!("No world at {}, starting a new world", dir.display());
+            Self::new_world(registry)
+        };
         let mut world = legion::World::default();
-        player::generate_main_player(&mut world);
+        let selected = blocks.registry().id_of("cobblestone").unwrap_or(BlockRegistry::AIR);
+        player::generate_main_player(&mut world, player::spawn_position(&mut blocks), selected);
 
         Self {
             blocks,
+            chunks: ChunkManager::new(ChunkManager::DEFAULT_UNLOAD_MARGIN),
             world,
             pre_collision_schedule: Game::generate_precollision_schedule(),
             post_collision_schedule: Game::generate_postcollision_schedule(),
@@ -45,12 +83,43 @@ impl Game {
         }
     }
 
+    // Renames the world directory to a free name next to it, panics if that fails since the world
+    // would otherwise be overwritten on exit
+    fn move_aside(dir: &Path) -> PathBuf {
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or_default();
+        let kept = (0..).map(|n| PathBuf::from(format!("{}.unreadable-{}-{}", dir.display(), stamp, n)))
+            .find(|path| !path.exists())
+            .unwrap();
+        if let Err(e) = fs::rename(dir, &kept) {
+            panic!("Unable to move the unreadable world {} aside: {}", dir.display(), e);
+        }
+        kept
+    }
+
+    fn new_world(registry: Arc<BlockRegistry>) -> WorldBlocks {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_nanos() as u64).unwrap_or_default();
+        let created = WorldBlocks::create(Path::new(Self::WORLD_DIR), registry.clone(), seed).or_else(|e| {
+            log::error!("Unable to create the world on disk, it will stay in memory: {:#}", e);
+            WorldBlocks::generated(registry.clone(), seed)
+        });
+        match created {
+            Ok(blocks) => {
+                log::info!("Generating world with seed {}", seed);
+                blocks
+            },
+            Err(e) => {
+                log::error!("Unable to generate terrain, using the test layout: {:#}", e);
+                WorldBlocks::test_layout(registry)
+            }
+        }
+    }
+
     pub fn generate_precollision_schedule() -> Schedule {
         let mut scheduler = legion::Schedule::builder();
 
         components::input::schedule(&mut scheduler);
 
-        return scheduler.build()
+        scheduler.build()
     }
 
     pub fn generate_postcollision_schedule() -> Schedule {
@@ -58,7 +127,21 @@ impl Game {
 
         components::spatial::schedule(&mut scheduler);
 
-        return scheduler.build()
+        scheduler.build()
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        self.blocks.registry()
+    }
+
+    pub fn save(&mut self) {
+        if let Err(e) = self.blocks.save(Path::new(Self::WORLD_DIR)) {
+            log::error!("Unable to save world: {:#}", e);
+        }
+    }
+
+    pub fn reset_deltatime(&mut self) {
+        self.last_tick = Instant::now();
     }
 
     pub fn tick(&mut self, input: Input) {
@@ -68,22 +151,32 @@ impl Game {
 
         // Prepare resources
         self.resources.insert(Time { dt: self.dt });
+        self.resources.insert(self.chunks.update(&self.world, &mut self.blocks));
         self.resources.insert(input);
 
         self.pre_collision_schedule.execute(&mut self.world, &mut self.resources);
 
         self.post_collision_schedule.execute(&mut self.world, &mut self.resources);
 
-        components::collision::block_collide(&mut self.world, &self.blocks);
+        components::collision::block_collide(&mut self.world, &mut self.blocks);
+
+        // Edits are seen by the meshing in the next get_renderables
+        if let Some(input) = self.resources.get::<Input>() {
+            components::block_edit::edit_blocks(&mut self.world, &mut self.blocks, &input);
+
+            if input.just_pressed(Action::ToggleMeshing) {
+                let mode = self.blocks.meshing_mode().toggled();
+                log::info!("Meshing mode {:?}", mode);
+                self.blocks.set_meshing_mode(mode);
+            }
+        }
     }
 
     pub fn get_renderables(&mut self) -> Renderables {
         // Get object with camera
         let (cam_pos, cam_dir) = self.get_camera();
 
-        let mut sentences = Vec::new();
-
-        sentences.push(Sentence {
+        let sentences = vec![Sentence {
             data: "Rotated Text".to_owned(),
             position: Vector3::new(11., 5., 10.),
             direction: Quaternion::new(1., 0.5, 0.5, 0.5),
@@ -93,13 +186,21 @@ impl Game {
                 scale: 2.,
                 affected_by_camera: true
             }
-        });
+        }];
         
+        let (visible_stacks, stack_meshes) = self.blocks.get_stack_meshes(cam_pos);
+        let block = coords::world_to_block(cam_pos);
+
         Renderables {
             cam_dir,
             cam_pos,
-            cubes: self.blocks.get_renderable_blocks(cam_pos),
+            cubes: Vec::new(),
+            visible_stacks,
+            stack_meshes,
             sentences,
+            targeted_block: self.get_target(),
+            biome: self.blocks.biome_at(block.x, block.z),
+            block_memory: self.blocks.memory_footprint(),
         }
     }
 
@@ -115,6 +216,13 @@ impl Game {
             panic!("More than one entity with a camera detected!");
         };
 
-        return (pos.vector, dir.vector);
+        (pos.vector, dir.vector)
+    }
+
+    // The block the camera's editor would break, nothing if the camera cannot edit blocks
+    fn get_target(&mut self) -> Option<BlockCoords> {
+        let mut query = <(&Camera, &BlockEditor, &Direction, &Position)>::query();
+        let (_cam, editor, dir, pos) = query.iter(&self.world).next()?;
+        self.blocks.raycast(pos, dir, editor.reach).map(|hit| hit.block)
     }
 }
\ No newline at end of file
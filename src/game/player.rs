@@ -1,20 +1,72 @@
 use cgmath::{ Vector3, Point3 };
 use legion::World;
 
-use super::components::{ spatial::*, input::*, collision::*};
+use super::components::{ spatial::*, input::*, collision::*, chunk_loader::*, block_edit::* };
+use super::generation::worldblocks::WorldBlocks;
+use super::blocks::BlockRegistry;
+use super::units::{ BlockCoords, BlockID };
 
 pub struct Camera;
 
-pub fn generate_main_player(world: &mut World) {
+const SPAWN_COLUMN: (i32, i32) = (10, 10);
+const FALLBACK_SPAWN_HEIGHT: i32 = 64; // For worlds without a generator
+const BOUNDS: Vector3<f32> = Vector3 { x: 1., y: 2., z: 1. };
+
+// Standing on the generated ground of the spawn column, or on whatever was built or grown on top of it
+pub fn spawn_position(blocks: &mut WorldBlocks) -> Point3<f32> {
+    let (x, z) = SPAWN_COLUMN;
+    let mut feet = match blocks.height_at(x, z) {
+        Some(height) => height + 1,
+        None => FALLBACK_SPAWN_HEIGHT
+    };
+    while blocks.get_block(BlockCoords::new(x, feet, z)).is_some_and(|id| id != BlockRegistry::AIR) {
+        feet += 1;
+    }
+
+    Point3 { x: x as f32, y: feet as f32 + BOUNDS.y, z: z as f32 }
+}
+
+pub fn generate_main_player(world: &mut World, position: Point3<f32>, selected: BlockID) {
     world.push((
-        Position { vector: Point3 { x: 10., y: 20., z: 10. }},
+        Position { vector: position },
         Velocity::zero(),
         Direction::zero(),
         Gravity,
-        HumanoidKeyboardMovement { speed: 0.1, jump_vel: 2. },
+        HumanoidKeyboardMovement::base(0.1, 2.),
         MouseLook::base(10., 1.),
         Camera,
-        BoxCollider { bounds: Vector3 { x: 1., y: 2., z: 1.} },
+        BoxCollider { bounds: BOUNDS },
         CollidesWithBlocks,
+        OnGround::default(),
+        ChunkLoader { load_radius: 5, sim_radius: 1 },
+        BlockEditor { reach: 6., selected },
     ));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn spawns_on_the_generated_ground() {
+        let registry = Arc::new(BlockRegistry::builtin());
+        let mut blocks = WorldBlocks::generated(registry.clone(), 42).unwrap();
+        let (x, z) = SPAWN_COLUMN;
+        let height = blocks.height_at(x, z).unwrap();
+
+        let position = spawn_position(&mut blocks);
+        let feet = (position.y - BOUNDS.y) as i32;
+        assert!(feet > height);
+        assert_eq!(blocks.get_block(BlockCoords::new(x, feet, z)), Some(BlockRegistry::AIR));
+        assert_ne!(blocks.get_block(BlockCoords::new(x, feet - 1, z)), Some(BlockRegistry::AIR));
+
+        // Built over, it stands on top
+        let stone = registry.id_of("stone").unwrap();
+        for y in feet..feet + 3 {
+            blocks.set_block(BlockCoords::new(x, y, z), stone);
+        }
+        assert_eq!(spawn_position(&mut blocks).y, (feet + 3) as f32 + BOUNDS.y);
+    }
+}
@@ -1,10 +1,16 @@
-use crate::graphics::{cube_render::cube_instance::CubeInstance, text_render::sentence::Sentence};
+use crate::graphics::{cube_render::{cube_instance::CubeInstance, stack_mesh::StackMesh}, text_render::sentence::Sentence};
 
-use super::units::{ PlayerDirection, EntityCoords };
+use super::generation::biome::Biome;
+use super::units::{ PlayerDirection, EntityCoords, StackCoords, BlockCoords };
 
 pub struct Renderables {
     pub cam_dir: PlayerDirection,
     pub cam_pos: EntityCoords,
-    pub cubes: Vec<CubeInstance>,
-    pub sentences: Vec<Sentence>
+    pub cubes: Vec<CubeInstance>, // Free standing cubes, world blocks are drawn from the stack meshes
+    pub visible_stacks: Vec<StackCoords>, // The renderer drops cached meshes of every other stack
+    pub stack_meshes: Vec<(StackCoords, StackMesh)>, // Only stacks that changed since they were last sent
+    pub sentences: Vec<Sentence>,
+    pub targeted_block: Option<BlockCoords>, // Outlined, the block the camera looks at within reach
+    pub biome: Option<Biome>, // At the camera, for the debug overlay
+    pub block_memory: usize, // Bytes held by the loaded stacks, for the debug overlay
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use cgmath::{ Vector3, Point2, Point3 };
+use cgmath::{ Vector3, Point3 };
 
 pub type Loc = i32;
 pub type BlockID = u8;
@@ -12,7 +12,3 @@ pub struct StackCoords {
     pub x: i32,
     pub z: i32,
 }
-
-pub fn to_block_coord(position: WorldCoords) -> BlockCoords{
-    return position.map(|c| c.floor() as i32);
-}
\ No newline at end of file
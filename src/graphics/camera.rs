@@ -1,10 +1,11 @@
-use cgmath::{ self, Point3, Vector2, Vector3, Zero };
+use cgmath::{ self, Point3, Vector2, Vector3 };
 use bytemuck;
 use wgpu::util::DeviceExt;
 
 use crate::game::renderables::Renderables;
 
 use super::projection::Projection;
+use super::frustum::Frustum;
 
 
 pub struct Camera {
@@ -14,7 +15,8 @@ pub struct Camera {
     pub bind_group_layout: wgpu::BindGroupLayout,
 
     proj: Projection,
-    screen_size: Vector2<u32>
+    screen_size: Vector2<u32>,
+    frustum: Frustum,
 }
 
 pub struct CameraInitials {
@@ -75,6 +77,8 @@ impl Camera {
 
         let screen_size = Vector2::new(screen_width, screen_height);
 
+        let frustum = Frustum::from_matrix(uniform.view_proj.into());
+
         Self {
             proj,
             uniform,
@@ -82,6 +86,7 @@ impl Camera {
             bind_group,
             bind_group_layout,
             screen_size,
+            frustum,
         }
     }
 
@@ -91,15 +96,26 @@ impl Camera {
             position + direction,
             cgmath::Vector3::unit_y());
 
-        return super::OPENGL_TO_WGPU_MATRIX * self.proj.calc_matrix() * view;
+        super::OPENGL_TO_WGPU_MATRIX * self.proj.calc_matrix() * view
     }
 
     pub fn update_camera(&mut self, queue: &wgpu::Queue, renderables: &Renderables, screen_width: u32, screen_height: u32) {
-        self.uniform.view_proj = self.build_view_projection_matrix(renderables.cam_pos, renderables.cam_dir).into();
+        let view_proj = self.build_view_projection_matrix(renderables.cam_pos, renderables.cam_dir);
+        self.uniform.view_proj = view_proj.into();
+        self.frustum = Frustum::from_matrix(view_proj);
         self.screen_size.x = screen_width;
         self.screen_size.y = screen_height;
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.proj.resize(width, height);
+    }
+
+    // What the camera saw at the last update
+    pub fn frustum(&self) -> &Frustum {
+        &self.frustum
+    }
 }
 
 impl CameraUniform {
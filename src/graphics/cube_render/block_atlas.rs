@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ bail, Context };
+use image::RgbaImage;
+use rect_packer::Packer;
+
+use crate::game::blocks::{ BlockFace, BlockRegistry };
+use crate::game::units::BlockID;
+
+// All block textures packed into one image, with the UV rectangle of every texture
+pub struct BlockAtlas {
+    pub size: u32,
+    pub data: Vec<u8>,
+    uvs: HashMap<String, [f32; 4]>, // x, y, width, height in 0..1
+}
+
+impl BlockAtlas {
+    pub const DEFAULT_DIR: &'static str = "resources/textures/blocks";
+    pub const MISSING: &'static str = "missing";
+    pub const FACE_COUNT: usize = (BlockID::MAX as usize + 1) * BlockFace::ALL.len();
+    const MIN_SIZE: u32 = 256;
+    const MAX_SIZE: u32 = 8192;
+    const PADDING: i32 = 2;
+    const RGBA_SIZE: usize = 4;
+
+    // Packs every png in the directory, named after the file stem
+    pub fn from_dir(dir: &Path) -> anyhow::Result<Self> {
+        let mut textures = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("Unable to read textures in {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("png") {
+                continue;
+            }
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_owned();
+            let image = image::open(&path).with_context(|| format!("Unable to load texture {}", path.display()))?;
+            textures.push((name, image.to_rgba8()));
+        }
+
+        Self::pack(textures)
+    }
+
+    // Atlas with only the missing texture, every face will show it
+    pub fn empty() -> Self {
+        Self::pack(Vec::new()).expect("The missing texture always fits")
+    }
+
+    pub fn pack(mut textures: Vec<(String, RgbaImage)>) -> anyhow::Result<Self> {
+        textures.push((Self::MISSING.to_owned(), Self::missing_texture()));
+        // Largest first packs tighter, names keep the layout the same between runs
+        textures.sort_by(|a, b| b.1.height().cmp(&a.1.height()).then(a.0.cmp(&b.0)));
+
+        let mut size = Self::MIN_SIZE;
+        let placements = loop {
+            if let Some(placements) = Self::try_pack(&textures, size) {
+                break placements;
+            }
+            if size >= Self::MAX_SIZE {
+                bail!("Block textures do not fit in a {}x{} atlas", Self::MAX_SIZE, Self::MAX_SIZE);
+            }
+            size *= 2;
+        };
+
+        let mut data = vec![0u8; (size * size) as usize * Self::RGBA_SIZE];
+        let mut uvs = HashMap::new();
+        for ((name, image), (x, y)) in textures.iter().zip(placements) {
+            for (row, pixels) in image.as_raw().chunks_exact(image.width() as usize * Self::RGBA_SIZE).enumerate() {
+                let start = ((y as usize + row) * size as usize + x as usize) * Self::RGBA_SIZE;
+                data[start..start + pixels.len()].copy_from_slice(pixels);
+            }
+
+            uvs.insert(name.clone(), [
+                x as f32 / size as f32,
+                y as f32 / size as f32,
+                image.width() as f32 / size as f32,
+                image.height() as f32 / size as f32,
+            ]);
+        }
+
+        Ok(Self { size, data, uvs })
+    }
+
+    fn try_pack(textures: &[(String, RgbaImage)], size: u32) -> Option<Vec<(i32, i32)>> {
+        let mut packer = Packer::new(rect_packer::Config {
+            width: size as i32,
+            height: size as i32,
+            border_padding: Self::PADDING,
+            rectangle_padding: Self::PADDING,
+        });
+
+        textures.iter()
+            .map(|(_, image)| packer.pack(image.width() as i32, image.height() as i32, false).map(|r| (r.x, r.y)))
+            .collect()
+    }
+
+    fn missing_texture() -> RgbaImage {
+        RgbaImage::from_fn(16, 16, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 { image::Rgba([255, 0, 255, 255]) } else { image::Rgba([0, 0, 0, 255]) }
+        })
+    }
+
+    pub fn uv(&self, name: &str) -> [f32; 4] {
+        match self.uvs.get(name) {
+            Some(uv) => *uv,
+            None => self.uvs[Self::MISSING]
+        }
+    }
+
+    // UV rectangle for every block id and face, indexed by id * 6 + face
+    pub fn face_table(&self, registry: &BlockRegistry) -> Vec<[f32; 4]> {
+        let mut table = vec![self.uv(Self::MISSING); Self::FACE_COUNT];
+        for definition in registry.definitions().filter(|d| d.is_visible()) {
+            for face in BlockFace::ALL {
+                let name = definition.texture(face);
+                if !self.uvs.contains_key(name) {
+                    log::warn!("Block {} uses missing texture {:?}", definition.name, name);
+                }
+                table[definition.id as usize * BlockFace::ALL.len() + face as usize] = self.uv(name);
+            }
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(name: &str, width: u32, height: u32, shade: u8) -> (String, RgbaImage) {
+        (name.to_owned(), RgbaImage::from_pixel(width, height, image::Rgba([shade, 0, 0, 255])))
+    }
+
+    // In pixels, x, y, width, height
+    fn rect(atlas: &BlockAtlas, name: &str) -> [u32; 4] {
+        atlas.uv(name).map(|c| (c * atlas.size as f32).round() as u32)
+    }
+
+    #[test]
+    fn packed_textures_do_not_overlap() {
+        let sizes = [(16, 16), (32, 32), (16, 48), (64, 16), (128, 128), (8, 8)];
+        let textures = (0..40)
+            .map(|i| {
+                let (width, height) = sizes[i % sizes.len()];
+                texture(&format!("t{}", i), width, height, i as u8 + 1)
+            })
+            .collect::<Vec<_>>();
+        let atlas = BlockAtlas::pack(textures.clone()).unwrap();
+        assert!(atlas.size > BlockAtlas::MIN_SIZE); // Had to grow
+        assert_eq!(atlas.data.len(), (atlas.size * atlas.size) as usize * BlockAtlas::RGBA_SIZE);
+
+        let rects = textures.iter().map(|(name, image)| {
+            let [x, y, width, height] = rect(&atlas, name);
+            assert_eq!((width, height), image.dimensions());
+            assert!(x + width <= atlas.size && y + height <= atlas.size);
+
+            // The corners hold the texture's own colour
+            for (px, py) in [(x, y), (x + width - 1, y + height - 1)] {
+                let start = (py * atlas.size + px) as usize * BlockAtlas::RGBA_SIZE;
+                assert_eq!(atlas.data[start..start + 4], image.get_pixel(0, 0).0);
+            }
+            [x, y, width, height]
+        }).chain([rect(&atlas, BlockAtlas::MISSING)]).collect::<Vec<_>>();
+
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let apart = a[0] + a[2] <= b[0] || b[0] + b[2] <= a[0] || a[1] + a[3] <= b[1] || b[1] + b[3] <= a[1];
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn face_table_points_at_each_face_texture() {
+        let registry = BlockRegistry::parse("[air]\nid = 0\nsolid = false\ntransparent = true\n\
+                                             [log]\nid = 7\ntextures = top side bottom\n\
+                                             [odd]\nid = 9\ntextures = a b c d e gone\n").unwrap();
+        let textures = ["top", "side", "bottom", "a", "b", "c", "d", "e"].iter().enumerate()
+            .map(|(i, name)| texture(name, 16, 16, i as u8 + 1))
+            .collect();
+        let atlas = BlockAtlas::pack(textures).unwrap();
+        let table = atlas.face_table(&registry);
+        assert_eq!(table.len(), BlockAtlas::FACE_COUNT);
+
+        let face = |id: usize, face: BlockFace| table[id * BlockFace::ALL.len() + face as usize];
+        assert_eq!(face(7, BlockFace::Top), atlas.uv("top"));
+        assert_eq!(face(7, BlockFace::Bottom), atlas.uv("bottom"));
+        for side in [BlockFace::Front, BlockFace::Right, BlockFace::Back, BlockFace::Left] {
+            assert_eq!(face(7, side), atlas.uv("side"));
+        }
+        assert_ne!(atlas.uv("top"), atlas.uv("side"));
+
+        // Front, right, back, left, top, bottom in order, unknown names show the missing texture
+        for (side, name) in BlockFace::ALL.into_iter().zip(["a", "b", "c", "d", "e", BlockAtlas::MISSING]) {
+            assert_eq!(face(9, side), atlas.uv(name));
+        }
+        assert_eq!(face(0, BlockFace::Top), atlas.uv(BlockAtlas::MISSING)); // Invisible
+        assert_eq!(face(200, BlockFace::Top), atlas.uv(BlockAtlas::MISSING)); // Undefined
+    }
+}
@@ -1,25 +1,31 @@
 // CUBE RENDERER
 // Group 0: Camera
-// Group 1: Texture
+// Group 1: Texture atlas
+// Group 2: Face table
 
 
 // VERTEX
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) tex_pos: vec3<f32>,
-    @location(2) tex_offset: f32
+    @location(1) tex_pos: vec2<f32>,
+    @location(2) face: u32
 }
 
 struct InstanceInput {
-    @location(3) tex_index: f32,
+    @location(3) block: u32,
     @location(4) position: vec3<f32>,
 }
 
+struct MeshVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_pos: vec2<f32>, // In blocks, not limited to 0..1
+    @location(2) face: u32 // Already block * 6 + face
+}
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_pos: vec3<f32>,
-    @location(1) tex_offset: f32,
-    @location(2) tex_index: f32
+    @location(0) tex_pos: vec2<f32>,
+    @location(1) @interpolate(flat) tex_rect: vec4<f32>
 };
 
 struct CameraUniform {
@@ -27,9 +33,17 @@ struct CameraUniform {
     screen_size: vec2<f32>
 }
 
+// Atlas rectangle (x, y, width, height) of every face of every block, indexed by block * 6 + face
+struct FaceTable {
+    rects: array<vec4<f32>, 1536>
+}
+
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+@group(2) @binding(0)
+var<uniform> faces: FaceTable;
+
 @vertex
 fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
@@ -39,8 +53,18 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
 
     // Send to fragment
     out.tex_pos = model.tex_pos;
-    out.tex_offset = model.tex_offset;
-    out.tex_index = instance.tex_index;
+    out.tex_rect = faces.rects[instance.block * 6u + model.face];
+
+    return out;
+}
+
+@vertex
+fn vs_mesh(model: MeshVertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1);
+    out.tex_pos = model.tex_pos;
+    out.tex_rect = faces.rects[model.face];
 
     return out;
 }
@@ -54,5 +78,6 @@ var tex_sampler: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, tex_sampler, vec2(in.tex_pos.x + in.tex_offset, in.tex_pos.y + in.tex_index));
-}
\ No newline at end of file
+    // Merged faces span several blocks, repeat the texture once per block within its atlas cell
+    return textureSample(texture, tex_sampler, in.tex_rect.xy + fract(in.tex_pos) * in.tex_rect.zw);
+}
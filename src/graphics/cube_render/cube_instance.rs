@@ -1,20 +1,20 @@
 
 pub struct CubeInstance {
-    pub tex_index: u32,
+    pub block: u32, // Block id, selects the textures from the face table
     pub position: cgmath::Point3<f32>
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CubeInstanceRaw {
-    tex_index: f32,
+    block: u32,
     location: [f32; 3],
 }
 
 impl CubeInstance {
     pub fn to_raw(&self) -> CubeInstanceRaw {
         CubeInstanceRaw {
-            tex_index: self.tex_index as f32,
+            block: self.block,
             location: self.position.into(),
         }
     }
@@ -25,7 +25,7 @@ impl CubeInstanceRaw {
         array_stride: std::mem::size_of::<CubeInstanceRaw>() as wgpu::BufferAddress,
         step_mode: wgpu::VertexStepMode::Instance,
         attributes: &wgpu::vertex_attr_array![
-            3 => Float32,
+            3 => Uint32,
             4 => Float32x3
         ]
     };
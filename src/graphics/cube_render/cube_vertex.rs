@@ -5,7 +5,7 @@
 pub struct CubeVertex {
     position: [f32; 3],
     texture: [f32; 2],
-    tex_offset: [f32; 1]
+    face: u32 // BlockFace, which texture of the block to use
 }
 
 impl CubeVertex {
@@ -15,47 +15,47 @@ impl CubeVertex {
         attributes: &wgpu::vertex_attr_array![
             0 => Float32x3,
             1 => Float32x2,
-            2 => Float32
+            2 => Uint32
         ]
     };
 }
 
 pub const CUBE_VERTICES: &[CubeVertex] = &[
-    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [0.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [0.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [0.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [0.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [0.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [0.0 / 6.0] }, // Front
-    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [2.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [2.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [2.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [2.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [2.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [2.0 / 6.0] }, // Back
-    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [3.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [3.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [3.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [3.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [3.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [3.0 / 6.0] }, // Left
-    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [1.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [1.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [1.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [1.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [1.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [1.0 / 6.0] }, // Right
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [5.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [5.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [5.0 / 6.0] },
-    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [5.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [5.0 / 6.0] },
-    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [5.0 / 6.0] }, // Bottom
-    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0 / 6.0, 0.0], tex_offset: [4.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [4.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [4.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0 / 6.0, 1.0], tex_offset: [4.0 / 6.0] },
-    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0 / 6.0, 1.0], tex_offset: [4.0 / 6.0] },
-    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0 / 6.0, 0.0], tex_offset: [4.0 / 6.0] } // Top
+    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [1.0, 1.0], face: 0 },
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0, 1.0], face: 0 },
+    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0, 0.0], face: 0 },
+    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0, 0.0], face: 0 },
+    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0, 0.0], face: 0 },
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0, 1.0], face: 0 }, // Front
+    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [1.0, 1.0], face: 2 },
+    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [0.0, 1.0], face: 2 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [0.0, 0.0], face: 2 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [0.0, 0.0], face: 2 },
+    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [1.0, 0.0], face: 2 },
+    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [1.0, 1.0], face: 2 }, // Back
+    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0, 0.0], face: 3 },
+    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [1.0, 0.0], face: 3 },
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [1.0, 1.0], face: 3 },
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [1.0, 1.0], face: 3 },
+    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [0.0, 1.0], face: 3 },
+    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0, 0.0], face: 3 }, // Left
+    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [0.0, 0.0], face: 1 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0, 0.0], face: 1 },
+    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [0.0, 1.0], face: 1 },
+    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0, 1.0], face: 1 },
+    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [0.0, 1.0], face: 1 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0, 0.0], face: 1 }, // Right
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0, 0.0], face: 5 },
+    CubeVertex { position: [ 0.5, -0.5, -0.5], texture: [1.0, 0.0], face: 5 },
+    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0, 1.0], face: 5 },
+    CubeVertex { position: [ 0.5, -0.5,  0.5], texture: [1.0, 1.0], face: 5 },
+    CubeVertex { position: [-0.5, -0.5,  0.5], texture: [0.0, 1.0], face: 5 },
+    CubeVertex { position: [-0.5, -0.5, -0.5], texture: [0.0, 0.0], face: 5 }, // Bottom
+    CubeVertex { position: [ 0.5,  0.5, -0.5], texture: [1.0, 0.0], face: 4 },
+    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0, 0.0], face: 4 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0, 1.0], face: 4 },
+    CubeVertex { position: [-0.5,  0.5,  0.5], texture: [0.0, 1.0], face: 4 },
+    CubeVertex { position: [ 0.5,  0.5,  0.5], texture: [1.0, 1.0], face: 4 },
+    CubeVertex { position: [-0.5,  0.5, -0.5], texture: [0.0, 0.0], face: 4 } // Top
 ];
 
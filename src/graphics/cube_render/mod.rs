@@ -1,32 +1,89 @@
 mod cube_vertex;
 pub mod cube_instance;
+pub mod block_atlas;
+pub mod stack_mesh;
+
+use std::collections::{ HashMap, HashSet };
+use std::path::Path;
 
 use wgpu::util::DeviceExt;
 
+use crate::game::blocks::BlockRegistry;
+use crate::game::units::StackCoords;
 use super::texture2d::Texture2D;
+use super::frustum::Frustum;
+use super::dynamic_buffer::DynamicBuffer;
+use block_atlas::BlockAtlas;
+use stack_mesh::{ MeshVertex, StackMesh, StackMeshBuffers };
 use cube_vertex::{CubeVertex, CUBE_VERTICES};
 use cube_instance::{ CubeInstance, CubeInstanceRaw };
 
 pub struct CubeRenderer {
+    #[allow(dead_code)] // Kept alive with the pipelines built from it
     shader: wgpu::ShaderModule,
     vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    instance_buffer: DynamicBuffer,
     render_pipeline: wgpu::RenderPipeline,
-    texture_map: Texture2D
+    mesh_pipeline: wgpu::RenderPipeline,
+    texture_map: Texture2D,
+    face_table: wgpu::BindGroup,
+    stack_meshes: HashMap<StackCoords, StackMeshBuffers>,
 }
 
 impl CubeRenderer {
-    const MAX_INSTANCES: u64 = 10000;
+    const INITIAL_INSTANCES: u64 = 1024;
 
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat, camera_layout: &wgpu::BindGroupLayout) -> Self {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat, camera_layout: &wgpu::BindGroupLayout,
+               registry: &BlockRegistry) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("cube.wgsl"));
-        let texture_map = Texture2D::from_png("Some Texture", &device, &queue,
-         include_bytes!("../../../resources/textures/grass_full.png"), wgpu::FilterMode::Nearest);
+
+        // -- TEXTURE ATLAS --
+        let atlas = match BlockAtlas::from_dir(Path::new(BlockAtlas::DEFAULT_DIR)) {
+            Ok(atlas) => atlas,
+            Err(e) => {
+                log::error!("Unable to build block atlas: {:#}", e);
+                BlockAtlas::empty()
+            }
+        };
+        let texture_map = Texture2D::from_bytes("Block Atlas", device, queue, (atlas.size, atlas.size), &atlas.data,
+                                                wgpu::FilterMode::Nearest);
+
+        let face_table_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cube Face Table Buffer"),
+            contents: bytemuck::cast_slice(&atlas.face_table(registry)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let face_table_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cube Face Table Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+        });
+        let face_table = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cube Face Table Bind Group"),
+            layout: &face_table_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: face_table_buffer.as_entire_binding(),
+                }
+            ],
+        });
 
         let render_pipeline_layout = 
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Descriptor"),
-                bind_group_layouts: &[camera_layout, &Texture2D::get_layout(device, "Cube Texture Bind Group Layout")],
+                bind_group_layouts: &[camera_layout, &Texture2D::get_layout(device, "Cube Texture Bind Group Layout"),
+                                      &face_table_layout],
                 push_constant_ranges: &[]
             });
 
@@ -36,24 +93,97 @@ impl CubeRenderer {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Cube Instance Buffer"),
-            size: (std::mem::size_of::<f32>() as u64) * 4 * Self::MAX_INSTANCES,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false
-        });
+        let instance_buffer = DynamicBuffer::new(device, "Cube Instance Buffer", wgpu::BufferUsages::VERTEX,
+                                                 std::mem::size_of::<CubeInstanceRaw>() as u64 * Self::INITIAL_INSTANCES);
+
+        let render_pipeline = Self::create_pipeline(device, &render_pipeline_layout, &shader, format, "Cube Render Pipeline",
+                                                    "vs_main", &[CubeVertex::LAYOUT, CubeInstanceRaw::LAYOUT]);
+        let mesh_pipeline = Self::create_pipeline(device, &render_pipeline_layout, &shader, format, "Stack Mesh Render Pipeline",
+                                                  "vs_mesh", &[MeshVertex::LAYOUT]);
+
+        Self {
+            shader,
+            vertex_buffer,
+            render_pipeline,
+            mesh_pipeline,
+            texture_map,
+            face_table,
+            instance_buffer,
+            stack_meshes: HashMap::new(),
+        }
+    }
+
+    pub fn render(&mut self, render_pass: &mut wgpu::RenderPass, device: &wgpu::Device, queue: &wgpu::Queue,
+        camera: &wgpu::BindGroup, instances: &[CubeInstance]) {
+
+        if instances.is_empty() {
+            return;
+        }
+
+        // Update instances
+        let raw = instances.iter().map(|x| x.to_raw()).collect::<Vec<_>>();
+        self.instance_buffer.write(device, queue, &raw);
+
+        render_pass.set_pipeline(&self.render_pipeline);
+
+        // Bind Groups
+        render_pass.set_bind_group(0, camera, &[]); // Camera Uniform
+        render_pass.set_bind_group(1, &self.texture_map.bind_group, &[]);  // Texture
+        render_pass.set_bind_group(2, &self.face_table, &[]);  // Face Table
+
+        // Vertex Buffer
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice());
+
+        // Drawing
+        render_pass.draw(0..CUBE_VERTICES.len() as u32, 0..instances.len() as u32);
+    }
+
+    // Uploads the changed meshes and frees those of stacks that are no longer visible
+    pub fn update_meshes(&mut self, device: &wgpu::Device, visible: &[StackCoords], meshes: &[(StackCoords, StackMesh)]) {
+        let visible = visible.iter().collect::<HashSet<_>>();
+        self.stack_meshes.retain(|coords, _| visible.contains(coords));
+
+        for (coords, mesh) in meshes {
+            match StackMeshBuffers::new(device, mesh) {
+                Some(buffers) => { self.stack_meshes.insert(*coords, buffers); },
+                None => { self.stack_meshes.remove(coords); }
+            }
+        }
+    }
+
+    // Stacks outside of the camera's view are skipped
+    pub fn render_meshes(&self, render_pass: &mut wgpu::RenderPass, camera: &wgpu::BindGroup, frustum: &Frustum) {
+
+        render_pass.set_pipeline(&self.mesh_pipeline);
+
+        // Bind Groups
+        render_pass.set_bind_group(0, camera, &[]); // Camera Uniform
+        render_pass.set_bind_group(1, &self.texture_map.bind_group, &[]);  // Texture
+        render_pass.set_bind_group(2, &self.face_table, &[]);  // Face Table
+
+        for (coords, mesh) in &self.stack_meshes {
+            let (min, max) = mesh.bounds(*coords);
+            if frustum.intersects_box(min, max) {
+                mesh.draw(render_pass);
+            }
+        }
+    }
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Cube Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+    fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule,
+                       format: wgpu::TextureFormat, label: &str, vertex_entry: &str,
+                       buffers: &[wgpu::VertexBufferLayout]) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[CubeVertex::LAYOUT, CubeInstanceRaw::LAYOUT],
+                module: shader,
+                entry_point: Some(vertex_entry),
+                buffers,
                 compilation_options: wgpu::PipelineCompilationOptions::default()
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
                     format,
@@ -85,37 +215,6 @@ impl CubeRenderer {
             },
             multiview: None,
             cache: None,
-        });
-
-        Self {
-            shader,
-            vertex_buffer,
-            render_pipeline,
-            texture_map,
-            instance_buffer
-        }
-    }
-
-    pub fn render(&self, render_pass: &mut wgpu::RenderPass, queue: &wgpu::Queue, camera: &wgpu::BindGroup,
-        instances: &Vec<CubeInstance>) {
-
-        // Update instances
-        let raw = instances.iter().map(|x| x.to_raw()).collect::<Vec<_>>();
-        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
-
-        render_pass.set_pipeline(&self.render_pipeline);
-
-        // Bind Groups
-        render_pass.set_bind_group(0, camera, &[]); // Camera Uniform
-        render_pass.set_bind_group(1, &self.texture_map.bind_group, &[]);  // Texture
-
-        // Vertex Buffer
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-
-        // Drawing
-        render_pass.draw(0..CUBE_VERTICES.len() as u32, 0..instances.len() as u32);
+        })
     }
 }
-
-
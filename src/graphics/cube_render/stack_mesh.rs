@@ -0,0 +1,100 @@
+use wgpu::util::DeviceExt;
+
+use crate::game::coords;
+use crate::game::units::{ StackCoords, WorldCoords };
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub tex_pos: [f32; 2],
+    pub face: u32 // Index into the face table, block id * 6 + BlockFace
+}
+
+impl MeshVertex {
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x2,
+            2 => Uint32
+        ]
+    };
+}
+
+// Triangles of every visible face in a stack, in world coordinates
+#[derive(Debug, Default)]
+pub struct StackMesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl StackMesh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    // Corners are top left, bottom left, bottom right, top right as seen from the front of the face
+    pub fn push_quad(&mut self, corners: [[f32; 3]; 4], tex_size: [f32; 2], face: u32) {
+        let start = self.vertices.len() as u32;
+        let tex = [[0., 0.], [0., tex_size[1]], [tex_size[0], tex_size[1]], [tex_size[0], 0.]];
+        for (position, tex_pos) in corners.into_iter().zip(tex) {
+            self.vertices.push(MeshVertex { position, tex_pos, face });
+        }
+        self.indices.extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+}
+
+// A stack mesh uploaded to the GPU, kept until the stack changes or leaves render distance
+pub struct StackMeshBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    heights: (f32, f32), // Lowest and highest vertex
+}
+
+impl StackMeshBuffers {
+    // None for meshes without any faces, wgpu does not allow binding empty buffers
+    pub fn new(device: &wgpu::Device, mesh: &StackMesh) -> Option<Self> {
+        if mesh.is_empty() {
+            return None;
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Stack Mesh Vertex Buffer"),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Stack Mesh Index Buffer"),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let heights = mesh.vertices.iter()
+            .fold((f32::MAX, f32::MIN), |(low, high), v| (low.min(v.position[1]), high.max(v.position[1])));
+
+        Some(Self { vertex_buffer, index_buffer, index_count: mesh.indices.len() as u32, heights })
+    }
+
+    // Box around the faces of the stack, much lower than the whole column as empty sections have none
+    pub fn bounds(&self, coords: StackCoords) -> (WorldCoords, WorldCoords) {
+        let (min, max) = coords::stack_bounds(coords);
+        (WorldCoords { y: self.heights.0, ..min }, WorldCoords { y: self.heights.1, ..max })
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
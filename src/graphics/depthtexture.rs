@@ -1,8 +1,10 @@
 
 
 pub struct DepthTexture {
+    #[allow(dead_code)] // Only used through the view so far
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    #[allow(dead_code)] // For sampling depth in a later pass
     pub sampler: wgpu::Sampler
 }
 
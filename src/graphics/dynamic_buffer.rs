@@ -0,0 +1,160 @@
+// Tracks how many bytes a GPU buffer can hold and how large it has to become to fit new data.
+// Kept apart from the buffer itself so the growth rules can be checked without a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferCapacity {
+    capacity: u64,
+    len: u64,
+}
+
+impl BufferCapacity {
+    pub const MIN_CAPACITY: u64 = 1024;
+    const GROWTH_FACTOR: u64 = 2;
+
+    pub fn new(capacity: u64) -> Self {
+        Self { capacity: Self::aligned(capacity.max(Self::MIN_CAPACITY)), len: 0 }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    // Bytes written by the last write
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Records a write of len bytes, returning the new capacity if the buffer has to be recreated.
+    // Grows geometrically so a slowly increasing amount of data does not reallocate every frame.
+    pub fn reserve(&mut self, len: u64) -> Option<u64> {
+        self.len = len;
+        if len <= self.capacity {
+            return None;
+        }
+
+        let mut capacity = self.capacity;
+        while capacity < len {
+            capacity *= Self::GROWTH_FACTOR;
+        }
+        self.capacity = Self::aligned(capacity);
+        Some(self.capacity)
+    }
+
+    // Buffer sizes and writes have to be a multiple of wgpu::COPY_BUFFER_ALIGNMENT
+    fn aligned(size: u64) -> u64 {
+        size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+    }
+}
+
+// GPU buffer that is rewritten with new contents every frame, recreated larger whenever they do not fit
+pub struct DynamicBuffer {
+    label: String,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    capacity: BufferCapacity,
+}
+
+impl DynamicBuffer {
+    pub fn new(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages, capacity: u64) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let capacity = BufferCapacity::new(capacity);
+        Self {
+            label: label.to_owned(),
+            usage,
+            buffer: Self::create_buffer(device, label, usage, capacity.capacity()),
+            capacity,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages, size: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false
+        })
+    }
+
+    // Replaces the contents of the buffer, the old contents are lost if it has to grow
+    pub fn write<T: bytemuck::Pod>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        if let Some(capacity) = self.capacity.reserve(bytes.len() as u64) {
+            log::debug!("Growing {} to {} bytes", self.label, capacity);
+            self.buffer = Self::create_buffer(device, &self.label, self.usage, capacity);
+        }
+
+        // Writes must be a multiple of the copy alignment, pad the tail with zeroes
+        let padding = (bytes.len() as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) as usize - bytes.len();
+        if padding == 0 {
+            queue.write_buffer(&self.buffer, 0, bytes);
+        } else {
+            let mut padded = bytes.to_vec();
+            padded.resize(bytes.len() + padding, 0);
+            queue.write_buffer(&self.buffer, 0, &padded);
+        }
+    }
+
+    // Only the part holding the last write, wgpu does not allow empty slices
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        debug_assert!(!self.capacity.is_empty(), "Slicing {} before anything was written", self.label);
+        self.buffer.slice(..self.capacity.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_at_the_minimum_and_aligned() {
+        assert_eq!(BufferCapacity::new(0).capacity(), BufferCapacity::MIN_CAPACITY);
+        assert_eq!(BufferCapacity::new(5000).capacity(), 5000);
+        assert_eq!(BufferCapacity::new(5001).capacity(), 5004);
+        assert!(BufferCapacity::new(5000).is_empty());
+    }
+
+    #[test]
+    fn writes_that_fit_keep_the_buffer() {
+        let mut capacity = BufferCapacity::new(2048);
+        assert_eq!(capacity.reserve(2048), None);
+        assert_eq!(capacity.reserve(10), None);
+        assert_eq!((capacity.capacity(), capacity.len()), (2048, 10));
+        assert_eq!(capacity.reserve(0), None);
+        assert!(capacity.is_empty());
+    }
+
+    #[test]
+    fn grows_geometrically() {
+        let mut capacity = BufferCapacity::new(1024);
+        assert_eq!(capacity.reserve(1025), Some(2048));
+        assert_eq!(capacity.reserve(2000), None);
+        assert_eq!(capacity.reserve(2049), Some(4096));
+
+        // A much larger write doubles as often as it takes
+        assert_eq!(capacity.reserve(100_000), Some(131_072));
+        assert_eq!(capacity.len(), 100_000);
+
+        // Shrinking writes never shrink the buffer
+        assert_eq!(capacity.reserve(8), None);
+        assert_eq!(capacity.capacity(), 131_072);
+    }
+
+    #[test]
+    fn grown_capacities_stay_aligned() {
+        let mut capacity = BufferCapacity::new(1030);
+        assert_eq!(capacity.capacity(), 1032);
+        assert_eq!(capacity.reserve(1033), Some(2064));
+
+        for len in [2065, 5000, 12_345, 99_999] {
+            if let Some(grown) = capacity.reserve(len) {
+                assert_eq!(grown % wgpu::COPY_BUFFER_ALIGNMENT, 0);
+                assert!(grown >= len);
+            }
+            // A padded write of the last length still fits
+            assert!(len.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) <= capacity.capacity());
+        }
+    }
+}
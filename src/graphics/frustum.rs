@@ -0,0 +1,82 @@
+use cgmath::{ InnerSpace, Matrix4, Point3, Vector4 };
+
+// The volume the camera can see, as six planes facing inwards.
+// A point p is on the inside of a plane when plane.dot(p, 1) >= 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6], // Left, right, bottom, top, near, far
+}
+
+impl Frustum {
+    // Planes of a view projection matrix with wgpu's clip space, -w <= x, y <= w and 0 <= z <= w
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        // cgmath stores columns, row i is the i-th component of every column
+        let row = |i: usize| Vector4::new(view_proj.x[i], view_proj.y[i], view_proj.z[i], view_proj.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z]
+            .map(|plane| plane / plane.truncate().magnitude());
+        Self { planes }
+    }
+
+    // Conservative, boxes close to an edge of the frustum may be kept even though they are just outside of it
+    pub fn intersects_box(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal is the last one to leave it
+            let corner = Vector4::new(
+                if plane.x >= 0. { max.x } else { min.x },
+                if plane.y >= 0. { max.y } else { min.y },
+                if plane.z >= 0. { max.z } else { min.z },
+                1.,
+            );
+            plane.dot(corner) >= 0.
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ Deg, Vector3 };
+
+    use super::*;
+    use super::super::OPENGL_TO_WGPU_MATRIX;
+
+    // At the origin looking down -z, 70 degrees high and wide, from 0.1 to 100 blocks
+    fn frustum() -> Frustum {
+        let view = Matrix4::look_at_rh(Point3::new(0., 0., 0.), Point3::new(0., 0., -1.), Vector3::unit_y());
+        Frustum::from_matrix(OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(70.), 1., 0.1, 100.) * view)
+    }
+
+    fn sees(min: [f32; 3], max: [f32; 3]) -> bool {
+        frustum().intersects_box(min.into(), max.into())
+    }
+
+    #[test]
+    fn boxes_in_front_are_visible() {
+        assert!(sees([-1., -1., -11.], [1., 1., -9.]));
+        assert!(sees([3., 3., -20.], [4., 4., -19.]));
+    }
+
+    #[test]
+    fn boxes_behind_are_culled() {
+        assert!(!sees([-1., -1., 9.], [1., 1., 11.]));
+        assert!(!sees([-100., -100., 0.5], [100., 100., 50.]));
+    }
+
+    #[test]
+    fn boxes_to_the_side_are_culled() {
+        // At 10 blocks away the edges are 7 blocks from the centre
+        assert!(!sees([50., -1., -11.], [52., 1., -9.]));
+        assert!(!sees([-9., -1., -11.], [-8., 1., -9.]));
+        assert!(!sees([-1., 8., -11.], [1., 9., -9.]));
+        assert!(!sees([-1., -1., -130.], [1., 1., -120.]));
+    }
+
+    #[test]
+    fn boxes_straddling_a_plane_are_visible() {
+        assert!(sees([-10., -1., -11.], [-5., 1., -9.])); // Left
+        assert!(sees([-1., 5., -11.], [1., 10., -9.])); // Top
+        assert!(sees([-1., -1., -105.], [1., 1., -95.])); // Far
+        assert!(sees([-0.5, -0.5, -0.5], [0.5, 0.5, 0.5])); // Near, around the camera
+    }
+}
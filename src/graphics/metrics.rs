@@ -1,4 +1,4 @@
-use cgmath::{ Quaternion, Point3, Vector3, Vector4, Zero };
+use cgmath::{ Quaternion, Point3, Vector3, Vector4 };
 use std::time::{ Duration, Instant };
 
 use super::{ Graphics, Renderables, text_render::{ sentence::Sentence, text_style::TextStyle }};
@@ -57,5 +57,23 @@ impl Graphics {
             direction: Quaternion::new(1., 0., 0., 0.),
             text_style: self.metrics.metric_style.clone()
         });
+
+        // Biome
+        if let Some(biome) = renderables.biome {
+            renderables.sentences.push(Sentence {
+                data: format!("biome: {}", biome.name()),
+                position: Vector3::new(-1.0, 0.7, 0.1),
+                direction: Quaternion::new(1., 0., 0., 0.),
+                text_style: self.metrics.metric_style.clone()
+            });
+        }
+
+        // Memory held by blocks
+        renderables.sentences.push(Sentence {
+            data: format!("blocks: {} KiB", renderables.block_memory / 1024),
+            position: Vector3::new(-1.0, 0.6, 0.1),
+            direction: Quaternion::new(1., 0., 0., 0.),
+            text_style: self.metrics.metric_style.clone()
+        });
     }
 }
\ No newline at end of file
@@ -1,19 +1,24 @@
 mod texture2d;
 mod depthtexture;
+mod dynamic_buffer;
 pub mod text_render;
 mod camera;
+mod frustum;
 mod projection;
 mod metrics;
 pub mod cube_render;
+mod outline_render;
 
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow;
 use winit::{window::Window};
 
 use crate::game::renderables::Renderables;
+use crate::game::blocks::BlockRegistry;
 use cube_render::CubeRenderer;
+use outline_render::OutlineRenderer;
 use text_render::FontRenderer;
 use camera::{ Camera, CameraInitials };
 use depthtexture::DepthTexture;
@@ -23,6 +28,7 @@ use metrics::PersistentMetrics;
 pub struct Graphics {
     // WGPU stuff
     surface: wgpu::Surface<'static>, // Represents the surface to be drawn on
+    #[allow(dead_code)] // The config carries the format that is used
     surface_format: wgpu::TextureFormat,
     device: wgpu::Device, // Represents phycical device
     queue: wgpu::Queue,
@@ -33,6 +39,7 @@ pub struct Graphics {
     // My stuff
     depth_texture: DepthTexture,
     cube_renderer: CubeRenderer,
+    outline_renderer: OutlineRenderer,
     font_renderer: FontRenderer,
     pub camera: Camera,
 
@@ -42,7 +49,7 @@ pub struct Graphics {
 }
 
 impl Graphics {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+    pub async fn new(window: Arc<Window>, registry: &BlockRegistry) -> anyhow::Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -104,7 +111,9 @@ impl Graphics {
 
         let depth_texture = DepthTexture::new(&device, &config);
 
-        let cube_renderer = CubeRenderer::new(&device, &queue, config.format, &camera.bind_group_layout);
+        let cube_renderer = CubeRenderer::new(&device, &queue, config.format, &camera.bind_group_layout, registry);
+
+        let outline_renderer = OutlineRenderer::new(&device, config.format, &camera.bind_group_layout);
 
         let mut font_renderer = FontRenderer::new(&device, config.format, &camera.bind_group_layout);
         font_renderer.add_font(&device, &queue, "Arial", 100., include_bytes!("../../resources/fonts/arial.ttf"));
@@ -119,6 +128,7 @@ impl Graphics {
             window,
             depth_texture,
             cube_renderer,
+            outline_renderer,
             font_renderer,
             camera,
             metrics: PersistentMetrics::new(),
@@ -136,6 +146,7 @@ impl Graphics {
             self.is_surface_configured = true;
 
             self.depth_texture = DepthTexture::new(&self.device, &self.config);
+            self.camera.resize(width, height);
         }
     }
 
@@ -144,9 +155,12 @@ impl Graphics {
         graphics.metrics(renderables);
 
         // Render classes
-        graphics.cube_renderer.render(render_pass, &graphics.queue, &graphics.camera.bind_group, 
+        graphics.cube_renderer.render_meshes(render_pass, &graphics.camera.bind_group, graphics.camera.frustum());
+        graphics.cube_renderer.render(render_pass, &graphics.device, &graphics.queue, &graphics.camera.bind_group, 
                                      &renderables.cubes);
-        graphics.font_renderer.render_sentences(&renderables.sentences, render_pass, &graphics.queue, &graphics.camera.bind_group);
+        graphics.outline_renderer.render(render_pass, &graphics.queue, &graphics.camera.bind_group, renderables.targeted_block);
+        graphics.font_renderer.render_sentences(&renderables.sentences, render_pass, &graphics.device, &graphics.queue,
+                                                &graphics.camera.bind_group);
     }
 
     pub fn render(&mut self, renderables: &mut Renderables) -> Result<(), wgpu::SurfaceError> {
@@ -155,7 +169,10 @@ impl Graphics {
         self.last_frame = Instant::now();
 
 
-        self.camera.update_camera(&self.queue, &renderables, self.config.width, self.config.height);
+        self.camera.update_camera(&self.queue, renderables, self.config.width, self.config.height);
+
+        // Before anything can skip the frame, changed meshes are only sent once
+        self.cube_renderer.update_meshes(&self.device, &renderables.visible_stacks, &renderables.stack_meshes);
 
         if !self.is_surface_configured {  // Ensure that all WGPU processes are finished
             return Ok(());
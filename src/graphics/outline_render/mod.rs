@@ -0,0 +1,152 @@
+use wgpu::util::DeviceExt;
+
+use crate::game::units::BlockCoords;
+use super::texture2d::Texture2D;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineVertex {
+    position: [f32; 3],
+}
+
+impl OutlineVertex {
+    const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<OutlineVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x3
+        ]
+    };
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineInstance {
+    block: [f32; 3],
+}
+
+impl OutlineInstance {
+    const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<OutlineInstance>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            1 => Float32x3
+        ]
+    };
+}
+
+// The 12 edges of a block, as pairs of corners
+const OUTLINE_VERTICES: &[OutlineVertex] = &[
+    OutlineVertex { position: [0., 0., 0.] }, OutlineVertex { position: [1., 0., 0.] },
+    OutlineVertex { position: [1., 0., 0.] }, OutlineVertex { position: [1., 0., 1.] },
+    OutlineVertex { position: [1., 0., 1.] }, OutlineVertex { position: [0., 0., 1.] },
+    OutlineVertex { position: [0., 0., 1.] }, OutlineVertex { position: [0., 0., 0.] }, // Bottom
+    OutlineVertex { position: [0., 1., 0.] }, OutlineVertex { position: [1., 1., 0.] },
+    OutlineVertex { position: [1., 1., 0.] }, OutlineVertex { position: [1., 1., 1.] },
+    OutlineVertex { position: [1., 1., 1.] }, OutlineVertex { position: [0., 1., 1.] },
+    OutlineVertex { position: [0., 1., 1.] }, OutlineVertex { position: [0., 1., 0.] }, // Top
+    OutlineVertex { position: [0., 0., 0.] }, OutlineVertex { position: [0., 1., 0.] },
+    OutlineVertex { position: [1., 0., 0.] }, OutlineVertex { position: [1., 1., 0.] },
+    OutlineVertex { position: [1., 0., 1.] }, OutlineVertex { position: [1., 1., 1.] },
+    OutlineVertex { position: [0., 0., 1.] }, OutlineVertex { position: [0., 1., 1.] } // Sides
+];
+
+// Draws a wireframe box around the block the player is looking at
+pub struct OutlineRenderer {
+    vertex_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl OutlineRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("outline.wgsl"));
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Render Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[]
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Vertex Buffer"),
+            contents: bytemuck::cast_slice(OUTLINE_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        // Only ever one outline, rewritten whenever it is drawn
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Outline Instance Buffer"),
+            size: std::mem::size_of::<OutlineInstance>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[OutlineVertex::LAYOUT, OutlineInstance::LAYOUT],
+                compilation_options: wgpu::PipelineCompilationOptions::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture2D::DEPTH_FORMAT,
+                depth_write_enabled: false, // Lines in front of each other do not need sorting
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(), // Biased in the shader, see outline.wgsl
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            vertex_buffer,
+            instance_buffer,
+            render_pipeline,
+        }
+    }
+
+    // Draws nothing without a target, must come after the blocks so they are in the depth buffer
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, queue: &wgpu::Queue, camera: &wgpu::BindGroup,
+                  target: Option<BlockCoords>) {
+        let Some(block) = target else {
+            return;
+        };
+
+        let instance = OutlineInstance { block: [block.x as f32, block.y as f32, block.z as f32] };
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&[instance]));
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera, &[]); // Camera Uniform
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.draw(0..OUTLINE_VERTICES.len() as u32, 0..1);
+    }
+}
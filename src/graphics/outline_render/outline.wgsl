@@ -0,0 +1,45 @@
+// OUTLINE RENDERER
+// Group 0: Camera
+
+
+// VERTEX
+struct VertexInput {
+    @location(0) position: vec3<f32>, // Corner of the unit cube
+}
+
+struct InstanceInput {
+    @location(1) block: vec3<f32>, // Lowest corner of the outlined block
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    screen_size: vec2<f32>
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Pulls the lines slightly towards the camera so they are not hidden by the faces they lie on.
+// Done here because the pipeline's depth bias only applies to triangles on most backends.
+const DEPTH_BIAS: f32 = 0.0005;
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = camera.view_proj * vec4<f32>(model.position + instance.block, 1);
+    out.clip_position.z -= DEPTH_BIAS * out.clip_position.w;
+
+    return out;
+}
+
+
+// FRAGMENT
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.05, 0.05, 0.05, 1.0);
+}
@@ -24,6 +24,6 @@ impl Projection {
     }
 
     pub fn calc_matrix(&self) -> cgmath::Matrix4<f32> {
-        return OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
     }
 }
\ No newline at end of file
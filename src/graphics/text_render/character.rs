@@ -1,4 +1,4 @@
-use cgmath::{ Vector2, Point2 } ;
+use cgmath::Vector2;
 
 pub struct FontCharacter {
     pub position: Vector2<f32>,
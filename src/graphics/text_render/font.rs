@@ -9,6 +9,7 @@ pub struct FontData {
 }
 
 impl FontData {
+    #[allow(dead_code)] // The font loader fills the fields itself
     pub fn new(texture: Texture2D, glyphs: HashMap<char, FontCharacter>) -> Self {
         Self {
             texture,
@@ -1,8 +1,7 @@
-use cgmath::{ Vector2, Vector3, Point2, Quaternion };
+use cgmath::{ Vector2, Vector3, Quaternion };
 
 use super::text_style::TextStyle;
 
-use super::super::text_render::FontRenderer;
 
 #[derive(Debug)]
 pub struct FontInstance {
@@ -12,11 +12,12 @@ use font::FontData;
 use sentence::Sentence;
 
 use super::texture2d::Texture2D;
+use super::dynamic_buffer::DynamicBuffer;
 
 use std::{collections::HashMap};
 
 use itertools::Itertools;
-use cgmath::{ ElementWise, Vector2, Vector3, Zero };
+use cgmath::{ Vector2, Vector3, Zero };
 use wgpu::{ Device, Queue, BindGroupLayout, util::DeviceExt };
 use ab_glyph::{ Font, FontRef, GlyphId, OutlinedGlyph, PxScaleFont, ScaleFont };
 use rect_packer::Packer;
@@ -25,16 +26,15 @@ pub struct FontRenderer {
     fonts: HashMap<String, FontData>,
 
     render_pipeline: wgpu::RenderPipeline,
-    shader: wgpu::ShaderModule,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    instance_buffer: DynamicBuffer,
 }
 
 impl FontRenderer {
     pub const FONT_ATLAS_SIZE: usize = 1024;
     pub const RGBA_SIZE: usize = 4;
-    pub const MAX_INSTANCES: usize = 1000;
+    pub const INITIAL_INSTANCES: usize = 1000;
     
     pub fn new(device: &Device, format: wgpu::TextureFormat, camera_layout: &BindGroupLayout) -> Self {
         // -- SHADER INIT --
@@ -53,12 +53,8 @@ impl FontRenderer {
             usage: wgpu::BufferUsages::INDEX
         });
 
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Font Instance Buffer"),
-            size: (std::mem::size_of::<FontInstanceRaw>() * Self::MAX_INSTANCES) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let instance_buffer = DynamicBuffer::new(device, "Font Instance Buffer", wgpu::BufferUsages::VERTEX,
+                                                 (std::mem::size_of::<FontInstanceRaw>() * Self::INITIAL_INSTANCES) as u64);
 
         // -- RENDER PIPELINE --
         let render_pipeline_layout = 
@@ -115,7 +111,6 @@ impl FontRenderer {
         Self {
             fonts: HashMap::new(),
             render_pipeline,
-            shader,
             vertex_buffer,
             index_buffer,
             instance_buffer
@@ -143,7 +138,7 @@ impl FontRenderer {
             let glyph = scaled_font.scaled_glyph(char);
 
             match scaled_font.outline_glyph(glyph) {
-                Some(outline) => self.pack_glpyh(&mut packer, scaled_font, &outline, char, id, &mut text_data, &mut glyphs),
+                Some(outline) => Self::pack_glpyh(&mut packer, scaled_font, &outline, char, id, &mut text_data, &mut glyphs),
                 None => {
                     glyphs.insert(char, FontCharacter { // Glyphs with no content (whitespace) have no outline
                         position: Vector2::new(0., 0.),
@@ -171,8 +166,8 @@ impl FontRenderer {
         });
     }
 
-    fn pack_glpyh(&mut self, packer: &mut Packer, scaled_font: PxScaleFont<&FontRef<'_>>, outline: &OutlinedGlyph, char: char,
-                  id: GlyphId, text_data: &mut Vec<u8>, glyphs: &mut HashMap<char, FontCharacter>) {
+    fn pack_glpyh(packer: &mut Packer, scaled_font: PxScaleFont<&FontRef<'_>>, outline: &OutlinedGlyph, char: char,
+                  id: GlyphId, text_data: &mut [u8], glyphs: &mut HashMap<char, FontCharacter>) {
         let (width, height) = (outline.px_bounds().width() as i32, outline.px_bounds().height() as i32);
             
         if let Some(rect) = packer.pack(width, height, false) {
@@ -186,7 +181,7 @@ impl FontRenderer {
             outline.draw(|x, y, c| {
                 let index = ((rect.x as u32 + x) + (rect.y as u32 + y) * Self::FONT_ATLAS_SIZE as u32) as usize;
 
-                text_data[index * Self::RGBA_SIZE + 0] = 255;
+                text_data[index * Self::RGBA_SIZE] = 255;
                 text_data[index * Self::RGBA_SIZE + 1] = 255;
                 text_data[index * Self::RGBA_SIZE + 2] = 255;
                 text_data[index * Self::RGBA_SIZE + 3] = (c * 255.0).clamp(0.0, 255.0) as u8; // If its larger than 1, multiplying it by 255 will overflow
@@ -194,8 +189,8 @@ impl FontRenderer {
         }
     }
 
-    pub fn render_sentences(&self, sentences: &Vec<Sentence>, render_pass: &mut wgpu::RenderPass,
-                         queue: &Queue, camera: &wgpu::BindGroup) {
+    pub fn render_sentences(&mut self, sentences: &Vec<Sentence>, render_pass: &mut wgpu::RenderPass,
+                         device: &Device, queue: &Queue, camera: &wgpu::BindGroup) {
         let mut instances = Vec::new();
         for sentence_data in sentences {
             let mut advance = 0.;
@@ -222,23 +217,34 @@ impl FontRenderer {
 
             instances.append(&mut sentence_drawn);
         }
-        self.render(render_pass, queue, camera, instances);
+        self.render(render_pass, device, queue, camera, instances);
     }
 
-    fn render(&self, render_pass: &mut wgpu::RenderPass, queue: &Queue, camera: &wgpu::BindGroup,
+    fn render(&mut self, render_pass: &mut wgpu::RenderPass, device: &Device, queue: &Queue, camera: &wgpu::BindGroup,
                  instances: Vec<FontInstance>) {
 
+        if instances.is_empty() {
+            return;
+        }
+
         // Prepare buffer and pipeline
         let instances_sorted = instances.iter()
             .sorted_by(|a, b| a.text_style.font.cmp(&b.text_style.font))
             .collect::<Vec<_>>();
 
+        // Create raw data and send to gpu, before binding as the buffer may be replaced when it grows
+        let raw = instances_sorted.iter()
+            .map(|x| x.to_raw())
+            .collect::<Vec<_>>();
+
+        self.instance_buffer.write(device, queue, &raw);
+
         render_pass.set_pipeline(&self.render_pipeline);
 
         render_pass.set_bind_group(0, camera, &[]);
 
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice());
 
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
 
@@ -248,13 +254,6 @@ impl FontRenderer {
             *num_fonts.entry(font).or_insert(0) += 1;
         }
 
-        // Create raw data and send to gpu
-        let raw = instances_sorted.iter()
-            .map(|x| x.to_raw())
-            .collect::<Vec<_>>();
-
-        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
-
         // Render each individual font glyph
         let mut prev = 0;
         for (font, size) in num_fonts {
@@ -2,8 +2,10 @@ use image;
 use cgmath::Vector2;
 
 pub struct Texture2D {
+    #[allow(dead_code)] // Only used through the bind group
     texture: wgpu::Texture,
     pub bind_group: wgpu::BindGroup,
+    #[allow(dead_code)] // Nothing needs the pixel size after upload yet
     pub size: Vector2<u32>
 }
 
@@ -35,6 +37,7 @@ impl Texture2D {
         })
     }
 
+    #[allow(dead_code)] // Textures are built from generated bytes so far
     pub fn from_png(label: &str, device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8],
                     filter: wgpu::FilterMode) -> Self {
         // Load image data into a variable and adjust
@@ -63,7 +66,7 @@ impl Texture2D {
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some(&label),
+            label: Some(label),
             view_formats: &[]
         });
 
@@ -75,7 +78,7 @@ impl Texture2D {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All
             },
-            &data, // The data itself
+            data, // The data itself
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * dimensions.0), // One byte for each r, g, b, a
@@ -97,7 +100,7 @@ impl Texture2D {
 
         // Define the actual data to be bound
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&label),
+            label: Some(label),
             layout: &Self::get_layout(device, label),
             entries: &[
                 wgpu::BindGroupEntry {
@@ -1,5 +1,6 @@
 pub mod graphics;
 mod application;
+mod bindings;
 mod util;
 mod game;
 
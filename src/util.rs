@@ -1,7 +1,7 @@
 use cgmath::Point3;
 
 pub fn lerp(value: f32, smooth_value: f32, alpha: f32) -> f32 {
-    return smooth_value * (1. - alpha) + value * alpha
+    smooth_value * (1. - alpha) + value * alpha
 }
 
 pub fn range3d(xi: (i32, i32), yi: (i32, i32), zi: (i32, i32)) -> Vec<Point3<i32>>{
@@ -14,4 +14,31 @@ pub fn range3d(xi: (i32, i32), yi: (i32, i32), zi: (i32, i32)) -> Vec<Point3<i32
         }
     }
     output
-}
\ No newline at end of file
+}
+
+// An empty directory for a test to write into, removed again when dropped
+#[cfg(test)]
+pub struct TestDir {
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        use std::sync::atomic::{ AtomicUsize, Ordering };
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!("minetest-{}-{}-{}", name, std::process::id(),
+                                                     COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}